@@ -0,0 +1,178 @@
+use clap::{AppSettings, ArgMatches, SubCommand};
+use failure::Fail;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tokens are treated as expired this many seconds early, so that a token is
+/// not handed out just before the authority stops accepting it.
+const REFRESH_MARGIN_SECS: u64 = 60;
+
+pub fn get_auth_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
+    SubCommand::with_name("auth")
+        .about("Manages authentication state")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("clear-cache").about("Removes all cached tokens"))
+}
+
+pub fn process_auth_subcommand<'a>(matches: &ArgMatches<'a>) -> Result<(), AuthError> {
+    if let ("clear-cache", _) = matches.subcommand() {
+        if TokenCache::clear().map_err(AuthError::ClearCache)? {
+            println!("token cache cleared");
+        } else {
+            println!("token cache already empty");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedToken {
+    access_token: String,
+
+    /// Seconds since the Unix epoch.
+    expires_at: u64,
+}
+
+/// Access tokens persisted between runs, keyed by the credentials that
+/// obtained them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenCache {
+    tokens: HashMap<String, CachedToken>,
+}
+
+impl TokenCache {
+    pub fn path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("slapper").join("tokens.json"))
+    }
+
+    /// Loads the cache, starting afresh if it is missing or unreadable.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let content = serde_json::to_vec(self)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // The mode only applies to new files, so tighten existing ones too.
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        options.open(&path)?.write_all(&content)
+    }
+
+    /// Removes the cache file, returning whether there was one.
+    pub fn clear() -> io::Result<bool> {
+        match Self::path() {
+            Some(ref path) if path.exists() => fs::remove_file(path).map(|_| true),
+            _ => Ok(false),
+        }
+    }
+
+    pub fn key(authority: &str, client_id: &str, audience: &str) -> String {
+        format!("{} {} {}", authority, client_id, audience)
+    }
+
+    /// Returns the cached token for the key, unless it is (nearly) expired.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tokens
+            .get(key)
+            .filter(|token| now_secs() + REFRESH_MARGIN_SECS < token.expires_at)
+            .map(|token| token.access_token.as_str())
+    }
+
+    pub fn insert(&mut self, key: String, access_token: String, expires_in: u64) {
+        let now = now_secs();
+        self.tokens.retain(|_, token| now < token.expires_at);
+        self.tokens.insert(
+            key,
+            CachedToken {
+                access_token,
+                expires_at: now + expires_in,
+            },
+        );
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use super::*;
+
+    fn cache_with(expires_at: u64) -> TokenCache {
+        let mut cache = TokenCache::default();
+        cache.tokens.insert(
+            "old".to_owned(),
+            CachedToken {
+                access_token: "old-token".to_owned(),
+                expires_at,
+            },
+        );
+        cache
+    }
+
+    #[test]
+    fn test_get_respects_refresh_margin() {
+        let now = now_secs();
+
+        assert_eq!(cache_with(now + 3600).get("old"), Some("old-token"));
+        assert_eq!(cache_with(now + REFRESH_MARGIN_SECS).get("old"), None);
+        assert_eq!(cache_with(now - 1).get("old"), None);
+        assert_eq!(cache_with(now + 3600).get("other"), None);
+    }
+
+    #[test]
+    fn test_insert_sets_expiry() {
+        let mut cache = TokenCache::default();
+        cache.insert("key".to_owned(), "token".to_owned(), 3600);
+
+        assert_eq!(cache.get("key"), Some("token"));
+        let expires_at = cache.tokens["key"].expires_at;
+        assert!(expires_at >= now_secs() + 3599 && expires_at <= now_secs() + 3600);
+
+        cache.insert("short".to_owned(), "token".to_owned(), REFRESH_MARGIN_SECS);
+        assert_eq!(cache.get("short"), None);
+    }
+
+    #[test]
+    fn test_insert_prunes_expired() {
+        let now = now_secs();
+        let mut expired = cache_with(now - 1);
+        expired.insert("key".to_owned(), "token".to_owned(), 3600);
+        assert!(!expired.tokens.contains_key("old"));
+
+        let mut live = cache_with(now + 30);
+        live.insert("key".to_owned(), "token".to_owned(), 3600);
+        assert!(live.tokens.contains_key("old"));
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum AuthError {
+    #[fail(display = "could not clear token cache: {}", _0)]
+    ClearCache(#[fail(cause)] io::Error),
+}
@@ -1,57 +1,301 @@
+use crate::migrate::{self, CURRENT_VERSION};
 use crate::project::{Endpoint, Environment, Project, ProjectMap};
 use failure::Fail;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use std::fs;
 use std::iter::FromIterator;
 use std::path::Path;
+use std::str::FromStr;
 use url::Url;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct Config {
+    /// The JSON Schema the file is written against, for editors.
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+
+    /// The layout version, so that older files can be upgraded on load.
+    #[serde(default = "current_version")]
+    pub version: u64,
+
+    /// Other config files to take projects from, as globs relative to this
+    /// file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    #[serde(default)]
     pub projects: ProjectMap,
 }
 
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
-        let file = File::open(path)
-            .map(BufReader::new)
-            .map_err(ConfigError::Io)?;
-        Ok(serde_json::from_reader::<_, Config>(file)
-            .map_err(|e| ConfigError::Deserialize(failure::Error::from(e)))?)
+        Self::from_file_with_format(path).map(|(config, _)| config)
+    }
+
+    /// Loads a config, also returning the format it was written in. Older
+    /// layouts are upgraded to the current one.
+    pub fn from_file_with_format<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<(Config, ConfigFormat), ConfigError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::sniff(&content));
+        let mut value = format.deserialize::<serde_json::Value>(&content)?;
+        migrate::migrate(&mut value)?;
+        let config = serde_json::from_value(value)
+            .map_err(|e| ConfigError::Deserialize(failure::Error::from(e)))?;
+        Ok((config, format))
+    }
+
+    pub fn to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ConfigFormat,
+    ) -> Result<(), ConfigError> {
+        let serialized = format.serialize(self)?;
+        fs::write(path, serialized).map_err(ConfigError::Io)
+    }
+}
+
+fn current_version() -> u64 {
+    CURRENT_VERSION
+}
+
+/// The file formats a config can be read from and written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "yaml", "toml"];
+
+    /// Picks a format from the file extension, if it is a known one.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the content itself.
+    ///
+    /// JSON is recognised by its leading brace and TOML by whether it parses
+    /// as such. Anything else is assumed to be YAML.
+    pub fn sniff(content: &str) -> Self {
+        if content.trim_start().starts_with('{') {
+            ConfigFormat::Json
+        } else if toml::from_str::<toml::Value>(content).is_ok() {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(self, content: &str) -> Result<T, ConfigError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(failure::Error::from),
+            ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(failure::Error::from),
+            ConfigFormat::Toml => toml::from_str(content).map_err(failure::Error::from),
+        }
+        .map_err(ConfigError::Deserialize)
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String, ConfigError> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(failure::Error::from),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(failure::Error::from),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(failure::Error::from),
+        }
+        .map_err(ConfigError::Serialize)
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = ConfigError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "json" => Ok(ConfigFormat::Json),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(ConfigError::UnknownFormat(input.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.extension())
     }
 }
 
 pub fn get_example_config() -> Config {
+    // The example projects have no auth, so there is nothing to inherit.
+    let environment = || Environment {
+        auth: None,
+        ..Environment::new(Url::parse("http://localhost:8000").unwrap())
+    };
+    let endpoint = |url_path| Endpoint {
+        auth: None,
+        ..Endpoint::new(url_path)
+    };
     Config {
-        projects: HashMap::from_iter(vec![
+        schema: None,
+        version: CURRENT_VERSION,
+        include: Vec::new(),
+        projects: IndexMap::from_iter(vec![
             (
                 String::from("project1"),
                 Project::from_full(
                     None,
-                    vec![(
-                        String::from("dev"),
-                        Environment::new(Url::parse("http://localhost:8000").unwrap()),
-                    )],
-                    vec![(String::from("some_object"), Endpoint::new("/{blah}"))],
+                    vec![(String::from("dev"), environment())],
+                    vec![(String::from("some_object"), endpoint("/{blah}"))],
                 ),
             ),
             (
                 String::from("project2"),
                 Project::from_full(
                     None,
-                    vec![(
-                        String::from("dev"),
-                        Environment::new(Url::parse("http://localhost:8000").unwrap()),
-                    )],
-                    vec![(String::from("some_other_object"), Endpoint::new("/"))],
+                    vec![(String::from("dev"), environment())],
+                    vec![(String::from("some_other_object"), endpoint("/"))],
                 ),
             ),
         ]),
     }
 }
 
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use crate::project::Body;
+
+    fn assert_round_trip(format: ConfigFormat) {
+        let mut config = get_example_config();
+        let mut endpoint = Endpoint::new("/orders");
+        endpoint.method = "POST".to_owned();
+        endpoint
+            .headers
+            .insert("Accept".to_owned(), "application/json".to_owned());
+        endpoint.query.insert("page".to_owned(), "1".to_owned());
+        endpoint.body = Some(Body::Inline {
+            content: serde_json::json!({"count": 2, "item": {"id": 1}}),
+            media: Some("application/json".to_owned()),
+        });
+        for project in config.projects.values_mut() {
+            project
+                .endpoints
+                .insert("create_order".to_owned(), endpoint.clone());
+        }
+
+        let serialized = format.serialize(&config).unwrap();
+        let loaded = format.deserialize::<Config>(&serialized).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&config).unwrap(),
+            serde_json::to_value(&loaded).unwrap()
+        );
+    }
+
+    fn assert_order_preserved(format: ConfigFormat) {
+        let environment = || Environment::new(Url::parse("http://localhost:8000").unwrap());
+        let project = Project::from_full(
+            None,
+            vec![
+                ("qa".to_owned(), environment()),
+                ("dev".to_owned(), environment()),
+            ],
+            vec![
+                ("zz".to_owned(), Endpoint::new("/zz")),
+                ("aa".to_owned(), Endpoint::new("/aa")),
+            ],
+        );
+        let config = Config {
+            schema: None,
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            projects: IndexMap::from_iter(vec![
+                ("zeta".to_owned(), project.clone()),
+                ("alpha".to_owned(), project),
+            ]),
+        };
+
+        let serialized = format.serialize(&config).unwrap();
+        let loaded = format.deserialize::<Config>(&serialized).unwrap();
+
+        assert_eq!(
+            loaded.projects.keys().collect::<Vec<_>>(),
+            vec!["zeta", "alpha"]
+        );
+        let project = &loaded.projects["zeta"];
+        assert_eq!(
+            project.environments.keys().collect::<Vec<_>>(),
+            vec!["qa", "dev"]
+        );
+        assert_eq!(
+            project.endpoints.keys().collect::<Vec<_>>(),
+            vec!["zz", "aa"]
+        );
+    }
+
+    #[test]
+    fn test_order_preserved_json() {
+        assert_order_preserved(ConfigFormat::Json);
+    }
+
+    #[test]
+    fn test_order_preserved_yaml() {
+        assert_order_preserved(ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_order_preserved_toml() {
+        assert_order_preserved(ConfigFormat::Toml);
+    }
+
+    #[test]
+    fn test_schema_key_kept() {
+        let content = r#"{"$schema": "./slapper.schema.json", "projects": {}}"#;
+
+        let config = ConfigFormat::Json.deserialize::<Config>(content).unwrap();
+
+        assert_eq!(config.schema.as_ref().unwrap(), "./slapper.schema.json");
+        let serialized = ConfigFormat::Json.serialize(&config).unwrap();
+        assert!(serialized.contains("\"$schema\""));
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        assert_round_trip(ConfigFormat::Json);
+    }
+
+    #[test]
+    fn test_round_trip_yaml() {
+        assert_round_trip(ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_round_trip_toml() {
+        assert_round_trip(ConfigFormat::Toml);
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum ConfigError {
     #[fail(display = "{}", _0)]
@@ -59,4 +303,52 @@ pub enum ConfigError {
 
     #[fail(display = "{}", _0)]
     Deserialize(#[fail(cause)] failure::Error),
+
+    #[fail(display = "{}", _0)]
+    Serialize(#[fail(cause)] failure::Error),
+
+    #[fail(display = "unknown config format: {}", _0)]
+    UnknownFormat(String),
+
+    #[fail(
+        display = "no config file found (looked for slapper.json, .yaml or .toml in this directory, its parents and the user config directory)"
+    )]
+    NotFound,
+
+    #[fail(
+        display = "config version {} is newer than this slapper supports ({})",
+        version, supported
+    )]
+    UnsupportedVersion { version: u64, supported: u64 },
+
+    #[fail(display = "{}: {}", path, cause)]
+    InFile {
+        path: String,
+        cause: Box<ConfigError>,
+    },
+
+    #[fail(display = "invalid include pattern '{}': {}", pattern, reason)]
+    InvalidInclude { pattern: String, reason: String },
+
+    #[fail(display = "{} includes itself", path)]
+    IncludeCycle { path: String },
+
+    #[fail(
+        display = "project '{}' is defined in both {} and {}",
+        name, first, second
+    )]
+    DuplicateProject {
+        name: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl ConfigError {
+    pub fn in_file(path: &Path, cause: ConfigError) -> Self {
+        ConfigError::InFile {
+            path: path.display().to_string(),
+            cause: Box::new(cause),
+        }
+    }
 }
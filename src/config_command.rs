@@ -0,0 +1,211 @@
+use crate::config::{Config, ConfigError, ConfigFormat};
+use crate::loader;
+use crate::migrate;
+use crate::project::Auth;
+use crate::secret::{self, Redactor, SecretError};
+use crate::validate::{self, Severity};
+use clap::{AppSettings, Arg, ArgMatches, SubCommand};
+use failure::Fail;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub fn get_config_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
+    SubCommand::with_name("config")
+        .about("Inspects the config files")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("path")
+                .about("Shows the config files that are loaded, in merge order"),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Prints the merged config")
+                .arg(
+                    Arg::with_name("FORMAT")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(ConfigFormat::NAMES)
+                        .default_value("json")
+                        .help("The format to print"),
+                )
+                .arg(
+                    Arg::with_name("RESOLVED")
+                        .long("resolved")
+                        .help("Resolves secret references, masking the secrets themselves"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("Upgrades the config files to the current layout, keeping .bak backups"),
+        )
+        .subcommand(SubCommand::with_name("schema").about("Prints a JSON Schema for config files"))
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks the config for problems, exiting non-zero if there are any")
+                .arg(
+                    Arg::with_name("STRICT")
+                        .long("strict")
+                        .help("Fails on warnings as well as errors"),
+                ),
+        )
+}
+
+pub fn process_config_subcommand<'a>(
+    matches: &ArgMatches<'a>,
+    explicit: Option<&Path>,
+) -> Result<(), ConfigCommandError> {
+    match matches.subcommand() {
+        ("path", _) => {
+            let loaded = loader::load(explicit).map_err(ConfigCommandError::Config)?;
+            for path in loaded.sources.iter().chain(&loaded.includes) {
+                println!("{}", path.display());
+            }
+        }
+        ("show", Some(matches)) => {
+            let mut config = loader::load(explicit)
+                .map_err(ConfigCommandError::Config)?
+                .config;
+            let resolved = matches.is_present("RESOLVED");
+            let redact = resolved || matches.is_present("REDACT");
+            for_each_auth(&mut config, |auth| {
+                *auth = shown_auth(auth, resolved, redact)?;
+                Ok(())
+            })
+            .map_err(ConfigCommandError::Secret)?;
+
+            let format = ConfigFormat::from_str(matches.value_of("FORMAT").unwrap())
+                .map_err(ConfigCommandError::Config)?;
+            let serialized = format
+                .serialize(&config)
+                .map_err(ConfigCommandError::Config)?;
+            println!("{}", serialized.trim_end());
+        }
+        ("migrate", _) => {
+            let loaded = loader::load(explicit).map_err(ConfigCommandError::Config)?;
+            for path in loaded.sources.iter().chain(&loaded.includes) {
+                migrate_file(path)
+                    .map_err(|e| ConfigCommandError::Config(ConfigError::in_file(path, e)))?;
+            }
+        }
+        ("schema", _) => {
+            let schema = schemars::schema_for!(Config);
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        }
+        ("validate", Some(matches)) => {
+            let loaded = loader::load(explicit).map_err(ConfigCommandError::Config)?;
+            let diagnostics = validate::validate(&loaded.config);
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+
+            let strict = matches.is_present("STRICT");
+            let failures = diagnostics
+                .iter()
+                .filter(|d| strict || d.severity == Severity::Error)
+                .count();
+            if failures > 0 {
+                return Err(ConfigCommandError::Invalid(failures));
+            }
+            let files = loaded.sources.len() + loaded.includes.len();
+            println!("config is valid ({} file(s) checked)", files);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Rewrites a file in the current layout, copying the original to `.bak`
+/// first. Files already up to date are left alone.
+fn migrate_file(path: &Path) -> Result<(), ConfigError> {
+    let (mut value, format) = loader::read_value(path)?;
+    let from = migrate::migrate(&mut value)?;
+    if from == migrate::CURRENT_VERSION {
+        println!("{}: already at version {}", path.display(), from);
+        return Ok(());
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    fs::copy(path, &backup).map_err(ConfigError::Io)?;
+    fs::write(path, format.serialize(&value)?).map_err(ConfigError::Io)?;
+    println!(
+        "{}: migrated from version {} to {} (backup in {})",
+        path.display(),
+        from,
+        migrate::CURRENT_VERSION,
+        Path::new(&backup).display()
+    );
+    Ok(())
+}
+
+/// Runs `f` over every auth in the config: project, environment and endpoint.
+/// The auth as `config show` prints it. Secrets are masked before resolving,
+/// so that a masked secret's command or file is never run or read.
+fn shown_auth(auth: &Auth, resolved: bool, redact: bool) -> Result<Auth, SecretError> {
+    let auth = if redact {
+        secret::mask_auth(auth)
+    } else {
+        auth.clone()
+    };
+    if resolved {
+        secret::resolve_auth(&auth, &mut Redactor::default())
+    } else {
+        Ok(auth)
+    }
+}
+
+fn for_each_auth<F>(config: &mut Config, mut f: F) -> Result<(), SecretError>
+where
+    F: FnMut(&mut Auth) -> Result<(), SecretError>,
+{
+    for project in config.projects.values_mut() {
+        let environments = project.environments.values_mut().map(|e| &mut e.auth);
+        let endpoints = project.endpoints.values_mut().map(|e| &mut e.auth);
+        for auth in std::iter::once(&mut project.auth)
+            .chain(environments)
+            .chain(endpoints)
+            .flatten()
+        {
+            f(auth)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod config_command_tests {
+    use super::*;
+
+    #[test]
+    fn test_masked_secrets_not_resolved() {
+        let auth = Auth::Basic {
+            username: "${env:SLAPPER_TEST_SHOW_USER}".to_owned(),
+            password: Some("${cmd:exit 1}".to_owned()),
+        };
+        std::env::set_var("SLAPPER_TEST_SHOW_USER", "me");
+
+        match shown_auth(&auth, true, true).unwrap() {
+            Auth::Basic { username, password } => {
+                assert_eq!(username, "me");
+                assert_eq!(password.unwrap(), "****");
+            }
+            other => panic!("expected basic auth, got {:?}", other),
+        }
+        assert!(shown_auth(&auth, true, false).is_err());
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ConfigCommandError {
+    #[fail(display = "{}", _0)]
+    Config(#[fail(cause)] ConfigError),
+
+    #[fail(display = "{}", _0)]
+    Secret(#[fail(cause)] SecretError),
+
+    #[fail(display = "config has {} problem(s)", _0)]
+    Invalid(usize),
+}
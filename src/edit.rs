@@ -0,0 +1,656 @@
+use crate::config::{Config, ConfigError, ConfigFormat};
+use crate::hit::{validate_method, Suggestion};
+use crate::loader;
+use crate::migrate::CURRENT_VERSION;
+use crate::parse::SubstitutingUrl;
+use crate::project::{Auth, Endpoint, Environment, Project};
+use clap::{AppSettings, Arg, ArgMatches, SubCommand};
+use failure::Fail;
+use indexmap::IndexMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::Url;
+
+pub fn get_edit_subcommands<'a, 'b>() -> Vec<clap::App<'a, 'b>> {
+    vec![
+        SubCommand::with_name("project")
+            .about("Adds, removes, renames and changes projects")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("Adds a project")
+                    .arg(name_arg("NAME", "The project name"))
+                    .arg(auth_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Removes a project")
+                    .arg(name_arg("NAME", "The project name")),
+            )
+            .subcommand(
+                SubCommand::with_name("rename")
+                    .about("Renames a project")
+                    .arg(name_arg("NAME", "The project name"))
+                    .arg(name_arg("NEW_NAME", "The new project name")),
+            )
+            .subcommand(
+                SubCommand::with_name("set")
+                    .about("Changes a project")
+                    .arg(name_arg("NAME", "The project name"))
+                    .arg(auth_arg())
+                    .arg(no_auth_arg()),
+            ),
+        SubCommand::with_name("env")
+            .about("Adds, removes, renames and changes environments")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("Adds an environment to a project")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The environment name"))
+                    .arg(
+                        Arg::with_name("URL")
+                            .required(true)
+                            .validator(validate_url)
+                            .help("The base URL of the environment"),
+                    )
+                    .arg(auth_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Removes an environment")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The environment name")),
+            )
+            .subcommand(
+                SubCommand::with_name("rename")
+                    .about("Renames an environment")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The environment name"))
+                    .arg(name_arg("NEW_NAME", "The new environment name")),
+            )
+            .subcommand(
+                SubCommand::with_name("set")
+                    .about("Changes an environment")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The environment name"))
+                    .arg(
+                        Arg::with_name("URL")
+                            .long("url")
+                            .takes_value(true)
+                            .validator(validate_url)
+                            .help("The base URL of the environment"),
+                    )
+                    .arg(auth_arg())
+                    .arg(no_auth_arg()),
+            ),
+        SubCommand::with_name("endpoint")
+            .about("Adds, removes, renames and changes endpoints")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("Adds an endpoint to a project")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The endpoint name"))
+                    .arg(
+                        Arg::with_name("PATH")
+                            .required(true)
+                            .validator(validate_path)
+                            .help("The path of the endpoint (e.g. /orders/{id})"),
+                    )
+                    .arg(method_arg())
+                    .arg(auth_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Removes an endpoint")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The endpoint name")),
+            )
+            .subcommand(
+                SubCommand::with_name("rename")
+                    .about("Renames an endpoint")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The endpoint name"))
+                    .arg(name_arg("NEW_NAME", "The new endpoint name")),
+            )
+            .subcommand(
+                SubCommand::with_name("set")
+                    .about("Changes an endpoint")
+                    .arg(name_arg("PROJECT", "The project name"))
+                    .arg(name_arg("NAME", "The endpoint name"))
+                    .arg(
+                        Arg::with_name("PATH")
+                            .long("path")
+                            .takes_value(true)
+                            .validator(validate_path)
+                            .help("The path of the endpoint"),
+                    )
+                    .arg(method_arg())
+                    .arg(auth_arg())
+                    .arg(no_auth_arg()),
+            ),
+    ]
+}
+
+fn name_arg<'a, 'b>(name: &'a str, help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name(name).required(true).help(help)
+}
+
+fn method_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("METHOD")
+        .short("m")
+        .long("method")
+        .takes_value(true)
+        .validator(validate_method)
+        .help("The HTTP method to use")
+}
+
+fn auth_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("AUTH")
+        .long("auth")
+        .takes_value(true)
+        .validator(validate_auth)
+        .help("The auth as JSON or YAML (e.g. \"{type: Bearer, values: {token: abc}}\")")
+}
+
+fn no_auth_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("NO_AUTH")
+        .long("no-auth")
+        .conflicts_with("AUTH")
+        .help("Removes the auth")
+}
+
+fn validate_url(url: String) -> Result<(), String> {
+    Url::parse(&url).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_path(path: String) -> Result<(), String> {
+    SubstitutingUrl::from_str(&path)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn validate_auth(auth: String) -> Result<(), String> {
+    parse_auth(&auth).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Auth is given as YAML, which also accepts JSON.
+fn parse_auth(auth: &str) -> Result<Auth, serde_yaml::Error> {
+    serde_yaml::from_str(auth)
+}
+
+/// The auth requested by `--auth` or `--no-auth`, if either was given.
+fn auth_change(matches: &ArgMatches) -> Option<Option<Auth>> {
+    if matches.is_present("NO_AUTH") {
+        Some(None)
+    } else {
+        matches
+            .value_of("AUTH")
+            .map(|auth| Some(parse_auth(auth).expect("auth validated but unparseable")))
+    }
+}
+
+pub fn process_edit_subcommand<'a>(
+    kind: &str,
+    matches: &ArgMatches<'a>,
+    explicit: Option<&Path>,
+) -> Result<(), EditError> {
+    let project = match matches.subcommand() {
+        (_, Some(matches)) if kind == "project" => matches.value_of("NAME"),
+        (_, Some(matches)) => matches.value_of("PROJECT"),
+        _ => return Ok(()),
+    };
+    let path = owning_file(explicit, project.unwrap())?;
+    let path = path.as_path();
+    let creating = kind == "project" && matches.subcommand_name() == Some("add");
+    let (mut config, format) = if creating && !path.exists() {
+        let config = Config {
+            schema: None,
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            projects: Default::default(),
+        };
+        let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Json);
+        (config, format)
+    } else {
+        Config::from_file_with_format(path)
+            .map_err(|e| EditError::Config(ConfigError::in_file(path, e)))?
+    };
+
+    match (kind, matches.subcommand()) {
+        ("project", (action, Some(matches))) => edit_project(&mut config, action, matches)?,
+        ("env", (action, Some(matches))) => edit_environment(&mut config, action, matches)?,
+        ("endpoint", (action, Some(matches))) => edit_endpoint(&mut config, action, matches)?,
+        _ => return Ok(()),
+    }
+
+    config.to_file(path, format).map_err(EditError::Config)
+}
+
+/// Finds the file to edit for a project: the one among the project config and
+/// its includes that defines it, or the project config for a new project.
+/// Projects defined only in another layer, such as the user config or a local
+/// override, are not edited there behind the user's back.
+fn owning_file(explicit: Option<&Path>, project: &str) -> Result<PathBuf, EditError> {
+    let primary = loader::primary_path(explicit);
+    if primary.exists() {
+        if let Some(path) = loader::project_file(&primary, project).map_err(EditError::Config)? {
+            return Ok(path);
+        }
+    }
+    for source in loader::discover(explicit) {
+        if source != primary
+            && loader::project_file(&source, project)
+                .map_err(EditError::Config)?
+                .is_some()
+        {
+            return Err(EditError::DefinedElsewhere {
+                name: project.to_owned(),
+                path: source.display().to_string(),
+            });
+        }
+    }
+    Ok(primary)
+}
+
+fn edit_project(config: &mut Config, action: &str, matches: &ArgMatches) -> Result<(), EditError> {
+    let name = matches.value_of("NAME").unwrap();
+    let projects = &mut config.projects;
+
+    match action {
+        "add" => {
+            ensure_absent(projects, "project", name)?;
+            let mut project = Project::new();
+            project.auth = auth_change(matches).unwrap_or(None);
+            projects.insert(name.to_owned(), project);
+        }
+        "remove" => {
+            lookup(projects, "project", name)?;
+            projects.retain(|k, _| k != name);
+        }
+        "rename" => {
+            let new_name = matches.value_of("NEW_NAME").unwrap();
+            lookup(projects, "project", name)?;
+            ensure_absent(projects, "project", new_name)?;
+            rename_key(projects, name, new_name);
+        }
+        "set" => {
+            let project = lookup(projects, "project", name)?;
+            if let Some(auth) = auth_change(matches) {
+                project.auth = auth;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn edit_environment(
+    config: &mut Config,
+    action: &str,
+    matches: &ArgMatches,
+) -> Result<(), EditError> {
+    let project = lookup(
+        &mut config.projects,
+        "project",
+        matches.value_of("PROJECT").unwrap(),
+    )?;
+    let name = matches.value_of("NAME").unwrap();
+    let new_auth = project.new_environment_auth();
+    let environments = &mut project.environments;
+
+    match action {
+        "add" => {
+            ensure_absent(environments, "environment", name)?;
+            let url = Url::parse(matches.value_of("URL").unwrap()).expect("URL validated");
+            let mut environment = Environment::new(url);
+            environment.auth = new_auth;
+            if let Some(auth) = auth_change(matches) {
+                environment.auth = auth;
+            }
+            environments.insert(name.to_owned(), environment);
+        }
+        "remove" => {
+            lookup(environments, "environment", name)?;
+            environments.retain(|k, _| k != name);
+        }
+        "rename" => {
+            let new_name = matches.value_of("NEW_NAME").unwrap();
+            lookup(environments, "environment", name)?;
+            ensure_absent(environments, "environment", new_name)?;
+            rename_key(environments, name, new_name);
+        }
+        "set" => {
+            let environment = lookup(environments, "environment", name)?;
+            if let Some(url) = matches.value_of("URL") {
+                environment.base_url = Url::parse(url).expect("URL validated");
+            }
+            if let Some(auth) = auth_change(matches) {
+                environment.auth = auth;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn edit_endpoint(config: &mut Config, action: &str, matches: &ArgMatches) -> Result<(), EditError> {
+    let project = lookup(
+        &mut config.projects,
+        "project",
+        matches.value_of("PROJECT").unwrap(),
+    )?;
+    let name = matches.value_of("NAME").unwrap();
+    let new_auth = project.new_endpoint_auth();
+    let endpoints = &mut project.endpoints;
+
+    match action {
+        "add" => {
+            ensure_absent(endpoints, "endpoint", name)?;
+            let mut endpoint = Endpoint::new(matches.value_of("PATH").unwrap());
+            if let Some(method) = matches.value_of("METHOD") {
+                endpoint.method = method.to_uppercase();
+            }
+            endpoint.auth = new_auth;
+            if let Some(auth) = auth_change(matches) {
+                endpoint.auth = auth;
+            }
+            endpoints.insert(name.to_owned(), endpoint);
+        }
+        "remove" => {
+            lookup(endpoints, "endpoint", name)?;
+            endpoints.retain(|k, _| k != name);
+        }
+        "rename" => {
+            let new_name = matches.value_of("NEW_NAME").unwrap();
+            lookup(endpoints, "endpoint", name)?;
+            ensure_absent(endpoints, "endpoint", new_name)?;
+            rename_key(endpoints, name, new_name);
+        }
+        "set" => {
+            let endpoint = lookup(endpoints, "endpoint", name)?;
+            if let Some(path) = matches.value_of("PATH") {
+                endpoint.url_path = path.to_owned();
+            }
+            if let Some(method) = matches.value_of("METHOD") {
+                endpoint.method = method.to_uppercase();
+            }
+            if let Some(auth) = auth_change(matches) {
+                endpoint.auth = auth;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn lookup<'a, V>(
+    map: &'a mut IndexMap<String, V>,
+    kind: &'static str,
+    name: &str,
+) -> Result<&'a mut V, EditError> {
+    if !map.contains_key(name) {
+        return Err(EditError::Unknown {
+            kind,
+            name: name.to_owned(),
+            suggestion: Suggestion::closest(name, map.keys()),
+        });
+    }
+    Ok(map.get_mut(name).unwrap())
+}
+
+fn ensure_absent<V>(
+    map: &IndexMap<String, V>,
+    kind: &'static str,
+    name: &str,
+) -> Result<(), EditError> {
+    if map.contains_key(name) {
+        return Err(EditError::AlreadyExists {
+            kind,
+            name: name.to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Renames a key while keeping its position in the map.
+fn rename_key<V>(map: &mut IndexMap<String, V>, name: &str, new_name: &str) {
+    *map = mem::replace(map, IndexMap::new())
+        .into_iter()
+        .map(|(k, v)| {
+            if k == name {
+                (new_name.to_owned(), v)
+            } else {
+                (k, v)
+            }
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod edit_tests {
+    use super::*;
+    use clap::App;
+    use std::env;
+    use std::fs;
+
+    fn edit_matches(args: &[&str]) -> ArgMatches<'static> {
+        App::new("slapper")
+            .subcommands(get_edit_subcommands())
+            .get_matches_from_safe(std::iter::once("slapper").chain(args.iter().cloned()))
+            .unwrap()
+    }
+
+    fn edit(config: &mut Config, args: &[&str]) -> Result<(), EditError> {
+        let matches = edit_matches(args);
+        let (kind, matches) = matches.subcommand();
+        let (action, matches) = matches.unwrap().subcommand();
+        let matches = matches.unwrap();
+        match kind {
+            "project" => edit_project(config, action, matches),
+            "env" => edit_environment(config, action, matches),
+            _ => edit_endpoint(config, action, matches),
+        }
+    }
+
+    fn sample() -> Config {
+        serde_json::from_str(
+            r#"{"projects": {
+                "first": {},
+                "shop": {
+                    "environments": {
+                        "dev": {"base_url": "http://localhost:8000/"},
+                        "prod": {"base_url": "https://shop.example/"}
+                    },
+                    "endpoints": {
+                        "orders": {"url_path": "/orders"},
+                        "order": {"url_path": "/orders/{id}"}
+                    }
+                },
+                "last": {}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rename_keeps_position() {
+        let mut config = sample();
+
+        edit(&mut config, &["project", "rename", "shop", "store"]).unwrap();
+        edit(&mut config, &["env", "rename", "store", "dev", "local"]).unwrap();
+        edit(
+            &mut config,
+            &["endpoint", "rename", "store", "orders", "all"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.projects.keys().collect::<Vec<_>>(),
+            vec!["first", "store", "last"]
+        );
+        let project = &config.projects["store"];
+        assert_eq!(
+            project.environments.keys().collect::<Vec<_>>(),
+            vec!["local", "prod"]
+        );
+        assert_eq!(
+            project.endpoints.keys().collect::<Vec<_>>(),
+            vec!["all", "order"]
+        );
+    }
+
+    #[test]
+    fn test_add_rejects_duplicates() {
+        let mut config = sample();
+
+        for args in &[
+            &["project", "add", "shop"][..],
+            &["env", "add", "shop", "dev", "http://localhost:1/"][..],
+            &["endpoint", "add", "shop", "orders", "/x"][..],
+            &["project", "rename", "first", "last"][..],
+        ] {
+            match edit(&mut config, args) {
+                Err(EditError::AlreadyExists { .. }) => {}
+                other => panic!("{:?} was accepted: {:?}", args, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_inherits_only_existing_auth() {
+        let mut config = sample();
+
+        edit(
+            &mut config,
+            &["env", "add", "shop", "qa", "http://localhost:1/"],
+        )
+        .unwrap();
+        edit(&mut config, &["endpoint", "add", "shop", "items", "/items"]).unwrap();
+        assert!(config.projects["shop"].environments["qa"].auth.is_none());
+        assert!(config.projects["shop"].endpoints["items"].auth.is_none());
+
+        edit(
+            &mut config,
+            &[
+                "project",
+                "set",
+                "first",
+                "--auth",
+                "{type: Bearer, values: {token: t}}",
+            ],
+        )
+        .unwrap();
+        edit(
+            &mut config,
+            &["env", "add", "first", "dev", "http://localhost:1/"],
+        )
+        .unwrap();
+        edit(
+            &mut config,
+            &["endpoint", "add", "first", "items", "/items"],
+        )
+        .unwrap();
+        let project = &config.projects["first"];
+        assert!(project.environments["dev"]
+            .auth
+            .as_ref()
+            .unwrap()
+            .is_inherit());
+        assert!(project.endpoints["items"]
+            .auth
+            .as_ref()
+            .unwrap()
+            .is_inherit());
+    }
+
+    #[test]
+    fn test_unknown_names_suggested() {
+        let mut config = sample();
+
+        let message = |config: &mut Config, args: &[&str]| match edit(config, args) {
+            Err(e @ EditError::Unknown { .. }) => e.to_string(),
+            other => panic!("expected an unknown name, got {:?}", other),
+        };
+        assert_eq!(
+            message(&mut config, &["project", "remove", "shob"]),
+            "unknown project 'shob' (did you mean 'shop'?)"
+        );
+        assert_eq!(
+            message(&mut config, &["env", "remove", "shop", "prd"]),
+            "unknown environment 'prd' (did you mean 'prod'?)"
+        );
+        assert_eq!(
+            message(
+                &mut config,
+                &["endpoint", "set", "shop", "ordrs", "-m", "GET"]
+            ),
+            "unknown endpoint 'ordrs' (did you mean 'orders'?)"
+        );
+    }
+
+    #[test]
+    fn test_file_written_in_original_format() {
+        let dir = env::temp_dir().join(format!("slapper-edit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("slapper.yaml");
+        fs::write(&path, "projects:\n  shop: {}\n").unwrap();
+
+        let matches = edit_matches(&["env", "add", "shop", "dev", "http://localhost:1/"]);
+        let (kind, matches) = matches.subcommand();
+        process_edit_subcommand(kind, matches.unwrap(), Some(&path)).unwrap();
+
+        let (config, format) = Config::from_file_with_format(&path).unwrap();
+        assert_eq!(format, ConfigFormat::Yaml);
+        assert!(
+            serde_yaml::from_str::<serde_yaml::Value>(&fs::read_to_string(&path).unwrap()).is_ok()
+        );
+        assert!(config.projects["shop"].environments.contains_key("dev"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_project_creates_file() {
+        let dir = env::temp_dir().join(format!("slapper-edit-new-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("slapper.toml");
+
+        let matches = edit_matches(&["project", "add", "shop"]);
+        let (kind, matches) = matches.subcommand();
+        process_edit_subcommand(kind, matches.unwrap(), Some(&path)).unwrap();
+
+        let (config, format) = Config::from_file_with_format(&path).unwrap();
+        assert_eq!(format, ConfigFormat::Toml);
+        assert!(config.projects.contains_key("shop"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum EditError {
+    #[fail(display = "{}", _0)]
+    Config(#[fail(cause)] ConfigError),
+
+    #[fail(display = "unknown {} '{}'{}", kind, name, suggestion)]
+    Unknown {
+        kind: &'static str,
+        name: String,
+        suggestion: Suggestion,
+    },
+
+    #[fail(display = "{} '{}' already exists", kind, name)]
+    AlreadyExists { kind: &'static str, name: String },
+
+    #[fail(
+        display = "project '{}' is defined in {}; edit it there or pass --config",
+        name, path
+    )]
+    DefinedElsewhere { name: String, path: String },
+}
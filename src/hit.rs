@@ -1,13 +1,22 @@
+use crate::auth::TokenCache;
 use crate::config::Config;
-use crate::parse::SubstitutingUrl;
-use crate::project::Auth;
+use crate::parse::{self, ParseError, SubstitutingUrl, SubstitutionError};
+use crate::project::{self, ApiKeyLocation, Auth, Body, ClientAuthentication, ValueMap};
+use crate::secret::{self, Redactor, SecretError};
 use clap::{Arg, ArgMatches, SubCommand};
-use http::header::CONTENT_TYPE;
+use failure::Fail;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, PROXY_AUTHORIZATION};
+use indexmap::IndexMap;
 use reqwest::{self, Client, Method, RequestBuilder};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
 use std::time::Instant;
+use url::form_urlencoded;
 
 pub fn get_hit_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
     SubCommand::with_name("hit")
@@ -22,41 +31,55 @@ pub fn get_hit_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
                 .required(true)
                 .help("The environment to load"),
         )
-        .arg(Arg::with_name("ENDPOINT").help("The named endpoint to hit"))
+        .arg(
+            Arg::with_name("ENDPOINT")
+                .required_unless("CUSTOM")
+                .help("The named endpoint to hit (with --custom, the first path value)"),
+        )
         .arg(
             Arg::with_name("CUSTOM")
                 .long("custom")
                 .takes_value(true)
-                .conflicts_with("ENDPOINT")
-                .help("A custom path to hit"),
+                .help("A custom path to hit, instead of a named endpoint"),
         )
         .arg(
             Arg::with_name("METHOD")
                 .short("m")
                 .long("method")
                 .takes_value(true)
+                .validator(validate_method)
                 .help("The HTTP method to use"),
         )
         .arg(
             Arg::with_name("MEDIA")
                 .long("media")
                 .takes_value(true)
-                .help("The media type of the request"),
+                .help("The media type of the request body"),
         )
         .arg(
             Arg::with_name("HEADER")
                 .long("header")
                 .takes_value(true)
                 .multiple(true)
+                .number_of_values(1)
                 .validator(validate_header)
-                .help("Additional headers (e.g. \"subscription-key: 1234\""),
+                .help("Additional headers, replacing configured ones (e.g. \"subscription-key: 1234\""),
+        )
+        .arg(
+            Arg::with_name("QUERY")
+                .long("query")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_var)
+                .help("Additional query parameters, replacing configured ones (e.g. \"page=2\")"),
         )
         .arg(
             Arg::with_name("DATA")
                 .short("d")
                 .long("data")
                 .takes_value(true)
-                .help("The body of the request"),
+                .help("The body of the request, instead of the configured one"),
         )
         .arg(
             Arg::with_name("DATA_FILE")
@@ -65,7 +88,36 @@ pub fn get_hit_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
                 .conflicts_with("DATA")
                 .help("File to read data from"),
         )
-        .arg(Arg::with_name("URL_VALUES").help("Variable values to pass to parsed URL"))
+        .arg(
+            Arg::with_name("VERBOSE")
+                .short("v")
+                .long("verbose")
+                .help("Prints the request headers"),
+        )
+        .arg(
+            Arg::with_name("NO_CACHE")
+                .long("no-cache")
+                .help("Fetches a fresh auth token instead of using or updating the cache"),
+        )
+        .arg(
+            Arg::with_name("VAR")
+                .long("var")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_var)
+                .help("A named path variable (e.g. \"id=42\")"),
+        )
+        .arg(
+            Arg::with_name("EXPLAIN")
+                .long("explain")
+                .help("Prints each variable's value and where it came from"),
+        )
+        .arg(
+            Arg::with_name("URL_VALUES")
+                .multiple(true)
+                .help("Values for the path variables not given by --var, in order"),
+        )
 }
 
 fn validate_header(header: String) -> Result<(), String> {
@@ -76,61 +128,443 @@ fn validate_header(header: String) -> Result<(), String> {
     }
 }
 
-pub fn process_hit_subcommand<'a>(matches: &ArgMatches<'a>, conf: Config) {
+fn validate_var(var: String) -> Result<(), String> {
+    match var.find('=') {
+        Some(pos) if pos > 0 => Ok(()),
+        _ => Err("expected a variable in the form name=value".into()),
+    }
+}
+
+pub(crate) fn validate_method(method: String) -> Result<(), String> {
+    Method::from_str(&method.to_uppercase())
+        .map(|_| ())
+        .map_err(|_| format!("invalid HTTP method: {}", method))
+}
+
+fn split_header(header: &str) -> (&str, &str) {
+    let pos = header
+        .find(':')
+        .expect("header validated without separator");
+    (header[..pos].trim(), header[pos + 1..].trim())
+}
+
+fn split_var(var: &str) -> (String, String) {
+    let pos = var.find('=').expect("variable validated without separator");
+    (var[..pos].to_owned(), var[pos + 1..].to_owned())
+}
+
+/// Where a variable's value came from, shown by `--explain`.
+#[derive(Debug, Clone, Copy)]
+enum VariableSource<'a> {
+    CommandLine,
+    Environment(&'a str),
+    Project(&'a str),
+}
+
+impl<'a> fmt::Display for VariableSource<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariableSource::CommandLine => f.write_str("command line"),
+            VariableSource::Environment(name) => write!(f, "environment '{}'", name),
+            VariableSource::Project(name) => write!(f, "project '{}'", name),
+        }
+    }
+}
+
+/// Assigns variable values, taking named values first and filling the path
+/// variables not given by name or by the config from the positional values,
+/// in order of appearance. Configured values are looked up in `layers` in
+/// order, for the path variables and the `template_names` used in headers and
+/// bodies.
+fn assign_variables<'a>(
+    path: &SubstitutingUrl,
+    template_names: &[String],
+    named: Vec<(String, String)>,
+    positional: Vec<String>,
+    layers: &[(VariableSource<'a>, &ValueMap)],
+) -> Result<IndexMap<String, (String, VariableSource<'a>)>, HitError> {
+    let mut names = Vec::new();
+    for name in path.variable_names() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let configured = |name: &str| {
+        layers
+            .iter()
+            .find_map(|(source, values)| values.get(name).map(|value| (value.clone(), *source)))
+    };
+
+    let unused = named
+        .iter()
+        .filter(|(name, _)| !names.contains(&name.as_str()) && !template_names.contains(name))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    if !unused.is_empty() {
+        return Err(HitError::UnusedVariables(NameList(unused)));
+    }
+
+    let mut values = named
+        .into_iter()
+        .map(|(name, value)| (name, (value, VariableSource::CommandLine)))
+        .collect::<IndexMap<_, _>>();
+    let free = names
+        .iter()
+        .filter(|name| !values.contains_key(**name) && configured(name).is_none())
+        .map(|name| (*name).to_owned())
+        .collect::<Vec<_>>();
+    if positional.len() > free.len() {
+        return Err(HitError::TooManyValues {
+            expected: free.len(),
+            given: positional.len(),
+        });
+    }
+    values.extend(
+        free.into_iter().zip(
+            positional
+                .into_iter()
+                .map(|value| (value, VariableSource::CommandLine)),
+        ),
+    );
+
+    let wanted = names
+        .iter()
+        .copied()
+        .chain(template_names.iter().map(String::as_str));
+    for name in wanted {
+        if !values.contains_key(name) {
+            if let Some(value) = configured(name) {
+                values.insert(name.to_owned(), value);
+            }
+        }
+    }
+
+    let missing = names
+        .iter()
+        .filter(|name| !values.contains_key(**name) && path.is_required(name))
+        .map(|name| (*name).to_owned())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(HitError::MissingVariables(NameList(missing)));
+    }
+
+    Ok(values)
+}
+
+/// Lays the command line headers over the configured ones. The body's media
+/// type is the Content-Type unless one is configured, `--media` overrides a
+/// configured one, and a `--header` overrides both.
+fn apply_cli_headers(
+    headers: &mut ValueMap,
+    cli_headers: &[(&str, &str)],
+    media: Option<&str>,
+    body_media: Option<&str>,
+) {
+    let content_type = CONTENT_TYPE.as_str();
+    if let Some(body_media) = body_media {
+        match media {
+            Some(media) => project::set_header(headers, content_type, media),
+            None if !headers.keys().any(|n| n.eq_ignore_ascii_case(content_type)) => {
+                project::set_header(headers, content_type, body_media)
+            }
+            None => {}
+        }
+    }
+    for (name, value) in cli_headers {
+        project::set_header(headers, name, value);
+    }
+}
+
+/// The positional path values. With `--custom` there is no endpoint name, so
+/// the value in its place is the first path value.
+fn positional_values(matches: &ArgMatches) -> Vec<String> {
+    let endpoint = match matches.value_of("CUSTOM") {
+        Some(_) => matches.value_of("ENDPOINT"),
+        None => None,
+    };
+    endpoint
+        .into_iter()
+        .chain(matches.values_of("URL_VALUES").unwrap_or_default())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads a configured body, along with its media type.
+fn load_body(body: &Body) -> Result<(Vec<u8>, String), HitError> {
+    match body {
+        Body::Inline { content, media } => {
+            let content = match content {
+                Value::String(text) => text.clone().into_bytes(),
+                other => other.to_string().into_bytes(),
+            };
+            let media = media
+                .clone()
+                .unwrap_or_else(|| media_from_content(&content).to_owned());
+            Ok((content, media))
+        }
+        Body::File { path, media } => {
+            let path = Path::new(path);
+            let content = fs::read(path).map_err(|cause| HitError::DataFile {
+                path: path.display().to_string(),
+                cause,
+            })?;
+            let media = media
+                .clone()
+                .unwrap_or_else(|| media_from_extension(path).to_owned());
+            Ok((content, media))
+        }
+    }
+}
+
+fn media_from_content(content: &[u8]) -> &'static str {
+    if serde_json::from_slice::<Value>(content).is_ok() {
+        "application/json"
+    } else {
+        "text/plain"
+    }
+}
+
+fn media_from_extension(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("yaml") | Some("yml") => "application/x-yaml",
+        Some("txt") => "text/plain",
+        Some("html") | Some("htm") => "text/html",
+        Some("csv") => "text/csv",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Sends the request. Secrets met along the way are added to the redactor, so
+/// that they can also be masked in any error that is returned.
+pub fn process_hit_subcommand<'a>(
+    matches: &ArgMatches<'a>,
+    conf: Config,
+    redactor: &mut Redactor,
+) -> Result<(), HitError> {
     let project_name = matches.value_of("PROJECT").unwrap();
     let environment_name = matches.value_of("ENVIRONMENT").unwrap();
-    let endpoint_name = matches.value_of("ENDPOINT").unwrap();
-    let project = &conf.projects[project_name];
-    let environment = &project.environments[environment_name];
-    let endpoint = &project.endpoints[endpoint_name];
-
-    let auth = match endpoint.auth {
-        Some(Auth::Inherit) => match environment.auth {
-            Some(Auth::Inherit) => &project.auth,
-            _ => &environment.auth,
-        },
-        _ => &endpoint.auth,
+    let project = conf
+        .projects
+        .get(project_name)
+        .ok_or_else(|| HitError::UnknownProject {
+            name: project_name.to_owned(),
+            suggestion: Suggestion::closest(project_name, conf.projects.keys()),
+        })?;
+    let environment =
+        project
+            .environments
+            .get(environment_name)
+            .ok_or_else(|| HitError::UnknownEnvironment {
+                project: project_name.to_owned(),
+                name: environment_name.to_owned(),
+                suggestion: Suggestion::closest(environment_name, project.environments.keys()),
+            })?;
+
+    let (url_path, configured_method, auth, endpoint) =
+        match matches.value_of("CUSTOM") {
+            Some(custom) => (custom, "GET", project.environment_auth(environment), None),
+            None => {
+                let endpoint_name = matches.value_of("ENDPOINT").unwrap();
+                let endpoint = project.endpoints.get(endpoint_name).ok_or_else(|| {
+                    HitError::UnknownEndpoint {
+                        project: project_name.to_owned(),
+                        name: endpoint_name.to_owned(),
+                        suggestion: Suggestion::closest(endpoint_name, project.endpoints.keys()),
+                    }
+                })?;
+                let auth = project.endpoint_auth(environment, endpoint);
+                (
+                    endpoint.url_path.as_str(),
+                    endpoint.method.as_str(),
+                    auth,
+                    Some(endpoint),
+                )
+            }
+        };
+
+    let method = matches.value_of("METHOD").unwrap_or(configured_method);
+    let method = Method::from_str(&method.to_uppercase())
+        .map_err(|_| HitError::InvalidMethod(method.to_owned()))?;
+
+    let given_body = match (matches.value_of("DATA"), matches.value_of("DATA_FILE")) {
+        (Some(data), _) => {
+            let content = data.as_bytes().to_vec();
+            let media = media_from_content(&content).to_owned();
+            Some((content, media))
+        }
+        (_, Some(path)) => load_body(&Body::File {
+            path: path.to_owned(),
+            media: None,
+        })
+        .map(Some)?,
+        _ => None,
     };
+    let configured_body = match given_body {
+        Some(_) => None,
+        None => endpoint
+            .and_then(|e| e.body.as_ref())
+            .map(load_body)
+            .transpose()?,
+    };
+    let configured_body_text = configured_body
+        .as_ref()
+        .and_then(|(content, _)| std::str::from_utf8(content).ok());
+    let configured_headers = project.request_headers(environment, endpoint);
+
+    let mut template_names = Vec::<String>::new();
+    let templates = configured_headers
+        .values()
+        .map(String::as_str)
+        .chain(configured_body_text);
+    for name in templates.flat_map(parse::template_names) {
+        if !template_names.iter().any(|n| n == name) {
+            template_names.push(name.to_owned());
+        }
+    }
 
-    let url_values = matches
-        .values_of("URL_VALUES")
+    let named_values = matches
+        .values_of("VAR")
         .unwrap_or_default()
-        .map(String::from)
+        .map(split_var)
         .collect::<Vec<_>>();
-    let parsed_path = SubstitutingUrl::from_str(&endpoint.url_path).expect("could not parse URL");
+    let url_values = positional_values(matches);
+    let layers = [
+        (
+            VariableSource::Environment(environment_name),
+            &environment.variables,
+        ),
+        (VariableSource::Project(project_name), &project.variables),
+    ];
+    let parsed_path = SubstitutingUrl::from_str(url_path).map_err(HitError::InvalidPath)?;
+    let assigned = assign_variables(
+        &parsed_path,
+        &template_names,
+        named_values,
+        url_values,
+        &layers,
+    )?;
+    if matches.is_present("EXPLAIN") {
+        println!("variables:");
+        for (name, (value, source)) in &assigned {
+            println!("  {} = {}  ({})", name, value, source);
+        }
+    }
+    let values = assigned
+        .into_iter()
+        .map(|(name, (value, _))| (name, value))
+        .collect::<HashMap<_, _>>();
+
     let subbed_path = parsed_path
-        .sub_by_index(url_values.into_iter())
-        .expect("could not sub variables");
+        .sub_by_name(&values)
+        .map_err(HitError::Substitution)?;
     let url = &environment
         .base_url
         .join(&subbed_path)
-        .expect("could not join path to URL");
+        .map_err(|cause| HitError::UrlJoin {
+            base_url: environment.base_url.to_string(),
+            path: subbed_path.to_string(),
+            cause,
+        })?;
 
-    let method = Method::from_str(&endpoint.method).unwrap_or_default();
+    let body = match configured_body {
+        Some((content, media)) => {
+            let content = match std::str::from_utf8(&content) {
+                Ok(text) => parse::substitute_template(text, &values)
+                    .into_owned()
+                    .into_bytes(),
+                Err(_) => content,
+            };
+            Some((content, media))
+        }
+        None => given_body,
+    };
+
+    let configured_headers = configured_headers
+        .into_iter()
+        .map(|(name, value)| {
+            let value = parse::substitute_template(&value, &values).into_owned();
+            (name, value)
+        })
+        .collect();
+    let mut headers = resolve_headers(configured_headers, redactor)?;
+    let cli_headers = matches
+        .values_of("HEADER")
+        .unwrap_or_default()
+        .map(split_header)
+        .collect::<Vec<_>>();
+    let body_media = body.as_ref().map(|(_, media)| media.as_str());
+    apply_cli_headers(
+        &mut headers,
+        &cli_headers,
+        matches.value_of("MEDIA"),
+        body_media,
+    );
+
+    let mut query = endpoint.map(|e| e.query.clone()).unwrap_or_default();
+    for (name, value) in matches
+        .values_of("QUERY")
+        .unwrap_or_default()
+        .map(split_var)
+    {
+        query.insert(name, value);
+    }
 
     let client = Client::new();
     let mut request = client.request(method, url.clone());
+    if !query.is_empty() {
+        request = request.query(&query.iter().collect::<Vec<_>>());
+    }
+
+    if let Some((content, _)) = body {
+        request = request.body(content);
+    }
+    for (name, value) in &headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
 
     let auth_start_time = Instant::now();
-    request = apply_auth(request, &auth);
+    let mut cache = if matches.is_present("NO_CACHE") {
+        None
+    } else {
+        Some(TokenCache::load())
+    };
+    request = apply_auth(request, auth, cache.as_mut(), redactor)?;
     let auth_end_time = Instant::now();
 
+    let request = request.build().map_err(HitError::Transport)?;
+    let sent_url = redactor.redact(request.url().as_str()).into_owned();
+    let mut sent_headers = String::new();
+    if matches.is_present("VERBOSE") {
+        for (name, value) in request.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            let value = if name == AUTHORIZATION || name == PROXY_AUTHORIZATION {
+                redactor.mask(&value)
+            } else {
+                redactor.redact(&value)
+            };
+            sent_headers.push_str(&format!("\n{}: {}", name, value));
+        }
+    }
+
     let request_start_time = Instant::now();
-    let mut response = request.send().unwrap();
+    let mut response = client.execute(request).map_err(HitError::Transport)?;
     let request_end_time = Instant::now();
 
     println!(
         r#"
 =================================
-url:    {0}
-status: {1}
+url:    {0}{1}
+status: {2}
 =================================
-auth duration:    {2:>12} ms
-request duration: {3:>12} ms
+auth duration:    {3:>12} ms
+request duration: {4:>12} ms
 ---------------------------------
-total:            {4:>12} ms
+total:            {5:>12} ms
 ================================="#,
-        url,
+        sent_url,
+        sent_headers,
         response.status(),
         auth_end_time.duration_since(auth_start_time).as_millis(),
         request_end_time
@@ -138,63 +572,481 @@ total:            {4:>12} ms
             .as_millis(),
         request_end_time.duration_since(auth_start_time).as_millis()
     );
-    let content = match response.headers().get(CONTENT_TYPE) {
-        Some(val) if val.to_str().unwrap().contains("json") => response
-            .json::<Value>()
-            .map(|x| serde_json::to_string_pretty(&x))
-            .unwrap()
-            .unwrap(),
-        _ => response.text().unwrap(),
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|val| val.to_str().ok())
+        .is_some_and(|val| val.contains("json"));
+    let content = if is_json {
+        let value = response.json::<Value>().map_err(HitError::Decode)?;
+        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
+    } else {
+        response.text().map_err(HitError::Decode)?
     };
     println!("{}", content);
+
+    Ok(())
+}
+
+/// Resolves secret references in configured header values. Values that came
+/// from a reference are added to the redactor.
+fn resolve_headers(headers: ValueMap, redactor: &mut Redactor) -> Result<ValueMap, HitError> {
+    let mut resolved = ValueMap::new();
+    for (name, value) in headers {
+        let plain = secret::resolve(&value)
+            .map_err(HitError::Secret)?
+            .into_owned();
+        let plain = if plain != value {
+            redactor.secret(plain)
+        } else {
+            plain
+        };
+        resolved.insert(name, plain);
+    }
+    Ok(resolved)
 }
 
-pub fn apply_auth(request: RequestBuilder, auth: &Option<Auth>) -> RequestBuilder {
-    match auth {
+/// Adds credentials to the request, fetching a token if the auth needs one.
+///
+/// Secret references in the auth are resolved here, and the secrets are added
+/// to the redactor. Tokens are taken from and added to the cache when one is
+/// given.
+pub fn apply_auth(
+    request: RequestBuilder,
+    auth: Option<&Auth>,
+    cache: Option<&mut TokenCache>,
+    redactor: &mut Redactor,
+) -> Result<RequestBuilder, HitError> {
+    let auth = auth
+        .map(|a| secret::resolve_auth(a, redactor))
+        .transpose()
+        .map_err(HitError::Secret)?;
+
+    match auth.as_ref() {
+        Some(Auth::Basic { username, password }) => {
+            Ok(request.basic_auth(username, password.as_ref()))
+        }
+        Some(Auth::Bearer { token }) => Ok(request.bearer_auth(token)),
+        Some(Auth::ApiKey {
+            name,
+            value,
+            location,
+        }) => Ok(match location {
+            ApiKeyLocation::Header => request.header(name.as_str(), value.as_str()),
+            ApiKeyLocation::Query => request.query(&[(name, value)]),
+        }),
         Some(Auth::ClientCredentials {
             authority,
             client_id,
             client_secret,
             grant_type,
             resource,
-            ..
+            scopes,
+            client_authentication,
         }) => {
-            let token = get_client_credentials_token(
+            let audience = [resource, scopes]
+                .iter()
+                .filter_map(|a| a.as_ref().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let key = TokenCache::key(authority, client_id, &audience);
+            if let Some(token) = cache.as_ref().and_then(|c| c.get(&key)) {
+                return Ok(request.bearer_auth(redactor.secret(token.to_owned())));
+            }
+
+            let (token, expires_in) = get_client_credentials_token(
                 authority,
                 client_id,
                 client_secret,
                 grant_type,
-                resource,
-            )
-            .unwrap();
-            request.bearer_auth(token)
+                resource.as_ref().map(String::as_str),
+                scopes.as_ref().map(String::as_str),
+                *client_authentication,
+            )?;
+            if let (Some(cache), Some(expires_in)) = (cache, expires_in) {
+                cache.insert(key, token.clone(), expires_in);
+                if let Err(e) = cache.save() {
+                    eprintln!("warning: could not save token cache: {}", e);
+                }
+            }
+            Ok(request.bearer_auth(redactor.secret(token)))
         }
-        _ => request,
+        Some(Auth::Inherit) | None => Ok(request),
     }
 }
 
+/// Requests a token, returning it with its lifetime in seconds, if given.
 fn get_client_credentials_token<'a>(
     authority: &'a str,
     client_id: &'a str,
     client_secret: &'a str,
     grant_type: &'a str,
-    resource: &'a str,
-) -> Option<String> {
-    let params = &[
-        ("client_id", client_id),
-        ("client_secret", client_secret),
-        ("grant_type", grant_type),
-        ("resource", resource),
-    ];
+    resource: Option<&'a str>,
+    scope: Option<&'a str>,
+    client_authentication: ClientAuthentication,
+) -> Result<(String, Option<u64>), HitError> {
+    let mut params = vec![("grant_type", grant_type)];
+    if let Some(resource) = resource {
+        params.push(("resource", resource));
+    }
+    if let Some(scope) = scope {
+        params.push(("scope", scope));
+    }
+
+    let mut request = Client::new().post(authority);
+    match client_authentication {
+        ClientAuthentication::Body => {
+            params.push(("client_id", client_id));
+            params.push(("client_secret", client_secret));
+        }
+        ClientAuthentication::Basic => {
+            // RFC 6749 has both parts form-encoded before they are joined.
+            let encode =
+                |s: &str| form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+            request = request.basic_auth(encode(client_id), Some(encode(client_secret)));
+        }
+    }
 
-    // TODO Handle errors.
-    Client::new()
-        .post(authority)
-        .form(params)
+    let auth_error = |reason: String| HitError::Auth {
+        authority: authority.to_owned(),
+        reason,
+    };
+
+    let mut response = request
+        .form(&params)
         .send()
-        .unwrap()
-        .json::<HashMap<String, String>>()
-        .unwrap()
+        .map_err(|e| auth_error(e.to_string()))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let reason = match response.json::<HashMap<String, Value>>() {
+            Ok(body) => match (
+                body.get("error").and_then(Value::as_str),
+                body.get("error_description").and_then(Value::as_str),
+            ) {
+                (Some(error), Some(description)) => format!("{}: {}", error, description),
+                (Some(error), None) => error.to_owned(),
+                _ => format!("token endpoint returned {}", status),
+            },
+            Err(_) => format!("token endpoint returned {}", status),
+        };
+        return Err(auth_error(reason));
+    }
+
+    let body = response
+        .json::<HashMap<String, Value>>()
+        .map_err(|e| auth_error(e.to_string()))?;
+    let token = body
         .get("access_token")
-        .map(|t| (*t).clone())
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| auth_error("no access_token in response".into()))?;
+    // Azure AD v1 endpoints send the lifetime as a string.
+    let expires_in = body.get("expires_in").and_then(|v| match v {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    });
+
+    Ok((token, expires_in))
+}
+
+/// A "did you mean" hint for a mistyped name, if a close enough one exists.
+#[derive(Debug)]
+pub struct Suggestion(Option<String>);
+
+impl Suggestion {
+    pub(crate) fn closest<'a, I>(name: &str, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let max_distance = (name.len() / 3).max(1);
+        let best = candidates
+            .into_iter()
+            .map(|c| (strsim::levenshtein(name, c), c))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance);
+        Suggestion(best.map(|(_, c)| c.clone()))
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Some(name) => write!(f, " (did you mean '{}'?)", name),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A list of names, displayed comma-separated.
+#[derive(Debug)]
+pub struct NameList(Vec<String>);
+
+impl fmt::Display for NameList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod hit_tests {
+    use super::*;
+    use clap::App;
+
+    fn hit_matches(args: &[&str]) -> ArgMatches<'static> {
+        let matches = App::new("slapper")
+            .subcommand(get_hit_subcommand())
+            .get_matches_from_safe(std::iter::once("slapper").chain(args.iter().cloned()))
+            .unwrap();
+        matches.subcommand_matches("hit").unwrap().clone()
+    }
+
+    fn content_type(
+        configured: &[(&str, &str)],
+        cli: &[(&str, &str)],
+        media: Option<&str>,
+    ) -> String {
+        let mut headers = configured
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect::<ValueMap>();
+        apply_cli_headers(&mut headers, cli, media, Some("text/plain"));
+        headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| v.clone())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn assign(
+        path: &str,
+        template_names: &[&str],
+        named: &[(&str, &str)],
+        positional: &[&str],
+    ) -> Result<Vec<String>, HitError> {
+        let environment = [("id", "env-id"), ("token", "env-token")]
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect::<ValueMap>();
+        let project = [
+            ("id", "project-id"),
+            ("page", "1"),
+            ("token", "project-token"),
+        ]
+        .iter()
+        .map(|(n, v)| (n.to_string(), v.to_string()))
+        .collect::<ValueMap>();
+        let layers = [
+            (VariableSource::Environment("dev"), &environment),
+            (VariableSource::Project("p"), &project),
+        ];
+        let values = assign_variables(
+            &SubstitutingUrl::from_str(path).unwrap(),
+            &template_names
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>(),
+            named
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            positional.iter().map(|v| v.to_string()).collect(),
+            &layers,
+        )?;
+        Ok(values
+            .iter()
+            .map(|(name, (value, source))| format!("{}={} ({})", name, value, source))
+            .collect())
+    }
+
+    #[test]
+    fn test_variable_precedence() {
+        assert_eq!(
+            assign("/{id}/{page}", &[], &[], &[]).unwrap(),
+            vec!["id=env-id (environment 'dev')", "page=1 (project 'p')"]
+        );
+        assert_eq!(
+            assign("/{id}/{page}", &[], &[("id", "42")], &[]).unwrap(),
+            vec!["id=42 (command line)", "page=1 (project 'p')"]
+        );
+    }
+
+    #[test]
+    fn test_positional_values_skip_configured() {
+        assert_eq!(
+            assign("/{id}/{order}/{page}/{line}", &[], &[], &["7", "3"]).unwrap(),
+            vec![
+                "order=7 (command line)",
+                "line=3 (command line)",
+                "id=env-id (environment 'dev')",
+                "page=1 (project 'p')",
+            ]
+        );
+        assert_eq!(
+            assign("/{order}/{line}", &[], &[("order", "7")], &["3"]).unwrap(),
+            vec!["order=7 (command line)", "line=3 (command line)"]
+        );
+    }
+
+    #[test]
+    fn test_too_many_values() {
+        match assign("/{id}/{order}", &[], &[], &["7", "8"]) {
+            Err(HitError::TooManyValues { expected, given }) => {
+                assert_eq!((expected, given), (1, 2))
+            }
+            other => panic!("expected too many values, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_variables() {
+        match assign("/{order}/{line}/{note?}", &[], &[], &["7"]) {
+            Err(HitError::MissingVariables(NameList(names))) => assert_eq!(names, vec!["line"]),
+            other => panic!("expected missing variables, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_template_only_names() {
+        assert_eq!(
+            assign("/{order}", &["token", "user"], &[("user", "me")], &["7"]).unwrap(),
+            vec![
+                "user=me (command line)",
+                "order=7 (command line)",
+                "token=env-token (environment 'dev')",
+            ]
+        );
+        match assign("/{order}", &["token"], &[("other", "x")], &["7"]) {
+            Err(HitError::UnusedVariables(NameList(names))) => assert_eq!(names, vec!["other"]),
+            other => panic!("expected unused variables, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_content_type_precedence() {
+        let configured = [("Content-Type", "application/xml")];
+        let cli = [("content-type", "text/csv")];
+
+        assert_eq!(content_type(&[], &[], None), "text/plain");
+        assert_eq!(content_type(&configured, &[], None), "application/xml");
+        assert_eq!(content_type(&configured, &[], Some("a/b")), "a/b");
+        assert_eq!(content_type(&configured, &cli, Some("a/b")), "text/csv");
+    }
+
+    #[test]
+    fn test_custom_takes_positional_values() {
+        let matches = hit_matches(&["hit", "p", "dev", "--custom", "/x/{id}/{n}", "42", "7"]);
+
+        assert_eq!(matches.value_of("CUSTOM"), Some("/x/{id}/{n}"));
+        assert_eq!(positional_values(&matches), vec!["42", "7"]);
+    }
+
+    #[test]
+    fn test_endpoint_not_a_value() {
+        let matches = hit_matches(&["hit", "p", "dev", "orders", "42"]);
+
+        assert_eq!(matches.value_of("ENDPOINT"), Some("orders"));
+        assert_eq!(positional_values(&matches), vec!["42"]);
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum HitError {
+    #[fail(display = "unknown project '{}'{}", name, suggestion)]
+    UnknownProject {
+        name: String,
+        suggestion: Suggestion,
+    },
+
+    #[fail(
+        display = "unknown environment '{}' in project '{}'{}",
+        name, project, suggestion
+    )]
+    UnknownEnvironment {
+        project: String,
+        name: String,
+        suggestion: Suggestion,
+    },
+
+    #[fail(
+        display = "unknown endpoint '{}' in project '{}'{}",
+        name, project, suggestion
+    )]
+    UnknownEndpoint {
+        project: String,
+        name: String,
+        suggestion: Suggestion,
+    },
+
+    #[fail(display = "could not parse path: {}", _0)]
+    InvalidPath(#[fail(cause)] ParseError),
+
+    #[fail(display = "could not substitute path variables: {}", _0)]
+    Substitution(#[fail(cause)] SubstitutionError),
+
+    #[fail(display = "missing values for path variables: {}", _0)]
+    MissingVariables(NameList),
+
+    #[fail(display = "path has no variables named: {}", _0)]
+    UnusedVariables(NameList),
+
+    #[fail(
+        display = "too many values: the path takes {} more positional value(s), {} given",
+        expected, given
+    )]
+    TooManyValues { expected: usize, given: usize },
+
+    #[fail(display = "could not join '{}' to '{}': {}", path, base_url, cause)]
+    UrlJoin {
+        base_url: String,
+        path: String,
+        #[fail(cause)]
+        cause: url::ParseError,
+    },
+
+    #[fail(display = "invalid HTTP method: {}", _0)]
+    InvalidMethod(String),
+
+    #[fail(display = "could not read data file '{}': {}", path, cause)]
+    DataFile {
+        path: String,
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
+    #[fail(display = "could not get auth token from {}: {}", authority, reason)]
+    Auth { authority: String, reason: String },
+
+    #[fail(display = "could not resolve secret: {}", _0)]
+    Secret(#[fail(cause)] SecretError),
+
+    #[fail(display = "request failed: {}", _0)]
+    Transport(#[fail(cause)] reqwest::Error),
+
+    #[fail(display = "could not decode response body: {}", _0)]
+    Decode(#[fail(cause)] reqwest::Error),
+}
+
+impl HitError {
+    /// The process exit code for this error, so scripts can tell failures apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HitError::UnknownProject { .. } => 10,
+            HitError::UnknownEnvironment { .. } => 11,
+            HitError::UnknownEndpoint { .. } => 12,
+            HitError::InvalidPath(_) => 13,
+            HitError::Substitution(_)
+            | HitError::MissingVariables(_)
+            | HitError::UnusedVariables(_)
+            | HitError::TooManyValues { .. } => 14,
+            HitError::UrlJoin { .. } => 15,
+            HitError::InvalidMethod(_) => 16,
+            HitError::DataFile { .. } => 17,
+            HitError::Auth { .. } => 20,
+            HitError::Secret(_) => 23,
+            HitError::Transport(_) => 21,
+            HitError::Decode(_) => 22,
+        }
+    }
 }
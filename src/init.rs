@@ -0,0 +1,198 @@
+use crate::config::{Config, ConfigError, ConfigFormat};
+use crate::migrate::CURRENT_VERSION;
+use crate::parse::SubstitutingUrl;
+use crate::project::{ApiKeyLocation, Auth, ClientAuthentication, Endpoint, Environment, Project};
+use clap::{ArgMatches, SubCommand};
+use console::Term;
+use dialoguer::{Confirmation, Input, PasswordInput, Select};
+use failure::Fail;
+use std::io;
+use std::path::Path;
+use url::Url;
+
+const METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+pub fn get_init_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
+    SubCommand::with_name("init").about("Interactively adds a project to the config file")
+}
+
+pub fn process_init_subcommand<'a>(
+    _matches: &ArgMatches<'a>,
+    path: &Path,
+) -> Result<(), InitError> {
+    if !Term::stderr().is_term() {
+        return Err(InitError::NotInteractive);
+    }
+
+    let (mut config, format) = if path.exists() {
+        Config::from_file_with_format(path).map_err(InitError::Config)?
+    } else {
+        let config = Config {
+            schema: None,
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            projects: Default::default(),
+        };
+        let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Json);
+        (config, format)
+    };
+
+    let name = Input::<String>::new()
+        .with_prompt("Project name")
+        .interact()?;
+    if config.projects.contains_key(&name)
+        && !Confirmation::new()
+            .with_text(&format!("Project '{}' already exists. Replace it?", name))
+            .default(false)
+            .interact()?
+    {
+        return Err(InitError::Aborted);
+    }
+
+    let mut project = Project::new();
+    project.auth = prompt_auth()?;
+
+    loop {
+        let name = Input::<String>::new()
+            .with_prompt("Environment name")
+            .default("dev".to_owned())
+            .interact()?;
+        let base_url = Input::<Url>::new().with_prompt("Base URL").interact()?;
+        let mut environment = Environment::new(base_url);
+        environment.auth = project.new_environment_auth();
+        project.environments.insert(name, environment);
+
+        if !Confirmation::new()
+            .with_text("Add another environment?")
+            .default(false)
+            .interact()?
+        {
+            break;
+        }
+    }
+
+    let endpoint_name = Input::<String>::new()
+        .with_prompt("Endpoint name")
+        .interact()?;
+    let url_path = Input::<SubstitutingUrl>::new()
+        .with_prompt("Endpoint path (e.g. /orders/{id})")
+        .interact()?;
+    let method = Select::new()
+        .with_prompt("Method")
+        .items(METHODS)
+        .default(0)
+        .interact()?;
+    let mut endpoint = Endpoint::new(&url_path.to_repr());
+    endpoint.method = METHODS[method].to_owned();
+    endpoint.auth = project.new_endpoint_auth();
+    project.endpoints.insert(endpoint_name, endpoint);
+
+    config.projects.insert(name, project);
+    config.to_file(path, format).map_err(InitError::Config)?;
+    println!("wrote {}", path.display());
+
+    Ok(())
+}
+
+fn prompt_auth() -> io::Result<Option<Auth>> {
+    let kinds = &[
+        "None",
+        "Client credentials",
+        "Basic",
+        "Bearer token",
+        "API key",
+    ];
+    let kind = Select::new()
+        .with_prompt("Authentication")
+        .items(kinds)
+        .default(0)
+        .interact()?;
+
+    let auth = match kind {
+        1 => Auth::ClientCredentials {
+            authority: Input::<Url>::new()
+                .with_prompt("Token endpoint")
+                .interact()?
+                .to_string(),
+            client_id: Input::<String>::new().with_prompt("Client ID").interact()?,
+            client_secret: secret("Client secret")?,
+            grant_type: Input::<String>::new()
+                .with_prompt("Grant type")
+                .default("client_credentials".to_owned())
+                .interact()?,
+            resource: optional("Resource (optional)")?,
+            scopes: optional("Scopes (optional)")?,
+            client_authentication: match Select::new()
+                .with_prompt("Send client credentials as")
+                .items(&["Form fields", "Basic header"])
+                .default(0)
+                .interact()?
+            {
+                0 => ClientAuthentication::Body,
+                _ => ClientAuthentication::Basic,
+            },
+        },
+        2 => Auth::Basic {
+            username: Input::<String>::new().with_prompt("Username").interact()?,
+            password: Some(secret("Password")?).filter(|p| !p.is_empty()),
+        },
+        3 => Auth::Bearer {
+            token: secret("Token")?,
+        },
+        4 => Auth::ApiKey {
+            name: Input::<String>::new()
+                .with_prompt("Key name")
+                .default("subscription-key".to_owned())
+                .interact()?,
+            value: secret("Key value")?,
+            location: match Select::new()
+                .with_prompt("Send key in")
+                .items(&["Header", "Query"])
+                .default(0)
+                .interact()?
+            {
+                0 => ApiKeyLocation::Header,
+                _ => ApiKeyLocation::Query,
+            },
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(auth))
+}
+
+/// Prompts for a secret without echoing it. References such as
+/// `${env:NAME}` can be entered instead of the secret itself.
+fn secret(prompt: &str) -> io::Result<String> {
+    PasswordInput::new().with_prompt(prompt).interact()
+}
+
+fn optional(prompt: &str) -> io::Result<Option<String>> {
+    let value = Input::<String>::new()
+        .with_prompt(prompt)
+        .default(String::new())
+        .show_default(false)
+        .interact()?;
+    Ok(Some(value).filter(|v| !v.is_empty()))
+}
+
+#[derive(Debug, Fail)]
+pub enum InitError {
+    #[fail(display = "init needs an interactive terminal")]
+    NotInteractive,
+
+    #[fail(display = "aborted")]
+    Aborted,
+
+    #[fail(display = "{}", _0)]
+    Prompt(#[fail(cause)] io::Error),
+
+    #[fail(display = "{}", _0)]
+    Config(#[fail(cause)] ConfigError),
+}
+
+impl From<io::Error> for InitError {
+    fn from(error: io::Error) -> Self {
+        InitError::Prompt(error)
+    }
+}
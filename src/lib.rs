@@ -1,4 +1,13 @@
+pub mod auth;
 pub mod config;
+pub mod config_command;
+pub mod edit;
 pub mod hit;
+pub mod init;
+pub mod list;
+pub mod loader;
+pub mod migrate;
 pub mod parse;
 pub mod project;
+pub mod secret;
+pub mod validate;
@@ -0,0 +1,320 @@
+use crate::config::Config;
+use crate::parse::SubstitutingUrl;
+use clap::{Arg, ArgMatches, SubCommand};
+use console::style;
+use serde::Serialize;
+use std::str::FromStr;
+
+pub fn get_list_subcommand<'a, 'b>() -> clap::App<'a, 'b> {
+    SubCommand::with_name("list")
+        .about("Lists projects, environments, and endpoints")
+        .arg(
+            Arg::with_name("PROJECT")
+                .short("p")
+                .long("project")
+                .takes_value(true)
+                .help("Only list the named project"),
+        )
+        .arg(
+            Arg::with_name("ENVIRONMENT")
+                .short("e")
+                .long("environment")
+                .takes_value(true)
+                .help("Only list the named environment"),
+        )
+        .arg(
+            Arg::with_name("SORT")
+                .short("s")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(&["declared", "name"])
+                .default_value("declared")
+                .help("The order to list items in"),
+        )
+        .arg(
+            Arg::with_name("JSON")
+                .long("json")
+                .help("Prints the listing as JSON"),
+        )
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectListing<'a> {
+    name: &'a str,
+    environments: Vec<EnvironmentListing<'a>>,
+    endpoints: Vec<EndpointListing<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnvironmentListing<'a> {
+    name: &'a str,
+    base_url: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct EndpointListing<'a> {
+    name: &'a str,
+    method: String,
+    url_path: &'a str,
+    variables: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+pub fn process_list_subcommand<'a>(matches: &ArgMatches<'a>, conf: Config) {
+    let mut listing = build_listing(
+        &conf,
+        matches.value_of("PROJECT"),
+        matches.value_of("ENVIRONMENT"),
+    );
+    if matches.value_of("SORT") == Some("name") {
+        sort_by_name(&mut listing);
+    }
+
+    if matches.is_present("JSON") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&listing).expect("could not serialize listing")
+        );
+    } else if listing.is_empty() {
+        eprintln!("no matching projects");
+    } else {
+        print_tree(&listing);
+    }
+}
+
+fn build_listing<'a>(
+    conf: &'a Config,
+    project_filter: Option<&str>,
+    environment_filter: Option<&str>,
+) -> Vec<ProjectListing<'a>> {
+    conf.projects
+        .iter()
+        .filter(|(name, _)| matches_filter(project_filter, name))
+        .filter_map(|(name, project)| {
+            let environments = project
+                .environments
+                .iter()
+                .filter(|(name, _)| matches_filter(environment_filter, name))
+                .map(|(name, environment)| EnvironmentListing {
+                    name,
+                    base_url: environment.base_url.as_str(),
+                })
+                .collect::<Vec<_>>();
+            if environments.is_empty() && environment_filter.is_some() {
+                return None;
+            }
+
+            let endpoints = project
+                .endpoints
+                .iter()
+                .map(|(name, endpoint)| {
+                    let (variables, error) = match SubstitutingUrl::from_str(&endpoint.url_path) {
+                        Ok(parsed) => (parsed.variable_names().map(String::from).collect(), None),
+                        Err(e) => (Vec::new(), Some(e.to_string())),
+                    };
+                    EndpointListing {
+                        name,
+                        method: endpoint.method.to_uppercase(),
+                        url_path: &endpoint.url_path,
+                        variables,
+                        error,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            Some(ProjectListing {
+                name,
+                environments,
+                endpoints,
+            })
+        })
+        .collect()
+}
+
+fn sort_by_name(projects: &mut [ProjectListing]) {
+    projects.sort_by_key(|p| p.name);
+    for project in projects {
+        project.environments.sort_by_key(|e| e.name);
+        project.endpoints.sort_by_key(|e| e.name);
+    }
+}
+
+fn matches_filter(filter: Option<&str>, name: &str) -> bool {
+    filter.is_none() || filter == Some(name)
+}
+
+fn print_tree(projects: &[ProjectListing]) {
+    for project in projects {
+        println!("{}", style(project.name).bold());
+
+        println!("├── environments");
+        for (i, environment) in with_last(&project.environments) {
+            let branch = if i { "└──" } else { "├──" };
+            println!(
+                "│   {} {}  {}",
+                branch,
+                style(environment.name).cyan(),
+                style(environment.base_url).dim()
+            );
+        }
+
+        println!("└── endpoints");
+        for (i, endpoint) in with_last(&project.endpoints) {
+            let branch = if i { "└──" } else { "├──" };
+            let detail = match &endpoint.error {
+                Some(error) => style(format!("invalid path: {}", error)).red().to_string(),
+                None if endpoint.variables.is_empty() => String::new(),
+                None => style(format!("[{}]", endpoint.variables.join(", ")))
+                    .yellow()
+                    .to_string(),
+            };
+            println!(
+                "    {} {}  {} {}{}{}",
+                branch,
+                style(endpoint.name).green(),
+                style(&endpoint.method).bold(),
+                endpoint.url_path,
+                if detail.is_empty() { "" } else { "  " },
+                detail
+            );
+        }
+    }
+}
+
+/// Pairs each item with whether it is the last one, for drawing branches.
+fn with_last<T>(items: &[T]) -> impl Iterator<Item = (bool, &T)> {
+    let last = items.len().saturating_sub(1);
+    items
+        .iter()
+        .enumerate()
+        .map(move |(i, item)| (i == last, item))
+}
+
+#[cfg(test)]
+mod list_tests {
+    use super::*;
+    use crate::config::ConfigFormat;
+
+    fn config() -> Config {
+        ConfigFormat::Yaml
+            .deserialize(
+                "projects:
+  shop:
+    environments:
+      prod: { base_url: 'https://shop.example/' }
+      dev: { base_url: 'http://localhost:8000/' }
+    endpoints:
+      orders: { url_path: '/orders' }
+      order: { url_path: '/orders/{id}/{line?}', method: delete }
+      broken: { url_path: '/orders/{id' }
+  admin:
+    environments:
+      prod: { base_url: 'https://admin.example/' }
+  docs:
+    environments:
+      dev: { base_url: 'http://localhost:9000/' }
+",
+            )
+            .unwrap()
+    }
+
+    fn names(listing: &[ProjectListing]) -> Vec<String> {
+        listing
+            .iter()
+            .map(|p| {
+                let environments = p.environments.iter().map(|e| e.name).collect::<Vec<_>>();
+                let endpoints = p.endpoints.iter().map(|e| e.name).collect::<Vec<_>>();
+                format!(
+                    "{} [{}] [{}]",
+                    p.name,
+                    environments.join(" "),
+                    endpoints.join(" ")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_declared_order() {
+        let config = config();
+
+        assert_eq!(
+            names(&build_listing(&config, None, None)),
+            vec![
+                "shop [prod dev] [orders order broken]",
+                "admin [prod] []",
+                "docs [dev] []",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let config = config();
+
+        assert_eq!(
+            names(&build_listing(&config, Some("shop"), None)),
+            vec!["shop [prod dev] [orders order broken]"]
+        );
+        assert_eq!(
+            names(&build_listing(&config, None, Some("dev"))),
+            vec!["shop [dev] [orders order broken]", "docs [dev] []"]
+        );
+        assert_eq!(
+            names(&build_listing(&config, Some("admin"), Some("dev"))),
+            Vec::<String>::new()
+        );
+        assert!(build_listing(&config, Some("nope"), None).is_empty());
+    }
+
+    #[test]
+    fn test_sort_by_name() {
+        let config = config();
+        let mut listing = build_listing(&config, None, None);
+
+        sort_by_name(&mut listing);
+
+        assert_eq!(
+            names(&listing),
+            vec![
+                "admin [prod] []",
+                "docs [dev] []",
+                "shop [dev prod] [broken order orders]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_shape() {
+        let config = config();
+        let listing = build_listing(&config, Some("shop"), Some("dev"));
+
+        let json = serde_json::to_value(&listing).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "name": "shop",
+                "environments": [{"name": "dev", "base_url": "http://localhost:8000/"}],
+                "endpoints": [
+                    {"name": "orders", "method": "GET", "url_path": "/orders", "variables": []},
+                    {
+                        "name": "order",
+                        "method": "DELETE",
+                        "url_path": "/orders/{id}/{line?}",
+                        "variables": ["id", "line"]
+                    },
+                    {
+                        "name": "broken",
+                        "method": "GET",
+                        "url_path": "/orders/{id",
+                        "variables": [],
+                        "error": SubstitutingUrl::from_str("/orders/{id").unwrap_err().to_string()
+                    }
+                ]
+            }])
+        );
+    }
+}
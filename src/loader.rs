@@ -0,0 +1,526 @@
+use crate::config::{Config, ConfigError, ConfigFormat};
+use crate::migrate;
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file names searched for, in order of preference.
+pub const CONFIG_NAMES: &[&str] = &[
+    "slapper.json",
+    "slapper.yaml",
+    "slapper.yml",
+    "slapper.toml",
+];
+
+/// A config merged from one or more files.
+pub struct LoadedConfig {
+    pub config: Config,
+
+    /// The files the config was merged from, in merge order.
+    pub sources: Vec<PathBuf>,
+
+    /// The files pulled in through `include`, in the order they were read.
+    pub includes: Vec<PathBuf>,
+}
+
+/// The extensions tried for a local override file.
+const LOCAL_EXTENSIONS: &[&str] = &["json", "yaml", "yml", "toml"];
+
+/// Finds the config files to load, in merge order.
+///
+/// An explicit path is used instead of searching. Otherwise the nearest config
+/// in the current directory or its parents is used, followed by the one in the
+/// user config directory, so that personal settings are layered over the
+/// project's. Either way, a `.local` sibling of the project config goes last.
+pub fn discover(explicit: Option<&Path>) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let project = match explicit {
+        Some(path) => Some(path.to_owned()),
+        None => project_config(),
+    };
+    if let Some(path) = &project {
+        found.push(path.clone());
+    }
+    if explicit.is_none() {
+        if let Some(path) = user_config_dir().and_then(|dir| find_in(&dir)) {
+            if !found.contains(&path) {
+                found.push(path);
+            }
+        }
+    }
+    if let Some(path) = project.as_ref().and_then(|path| local_override(path)) {
+        found.push(path);
+    }
+    found
+}
+
+/// Finds the uncommitted override next to a config, such as
+/// `slapper.local.yaml` for `slapper.json`.
+pub fn local_override(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    LOCAL_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.local.{}", stem, extension)))
+        .find(|path| path.is_file())
+}
+
+/// The file that edits should be written to: the explicit one, the nearest
+/// project config, or a new `slapper.json` in the current directory. The user
+/// config is never picked, so that edits meant for a project don't land there.
+pub fn primary_path(explicit: Option<&Path>) -> PathBuf {
+    explicit
+        .map(Path::to_owned)
+        .or_else(project_config)
+        .unwrap_or_else(|| PathBuf::from(CONFIG_NAMES[0]))
+}
+
+/// Finds the file among a config and its includes that defines a project.
+pub fn project_file(path: &Path, name: &str) -> Result<Option<PathBuf>, ConfigError> {
+    let mut origins = IndexMap::new();
+    read_with_includes(path, &mut origins, &mut Vec::new())?;
+    Ok(origins.swap_remove(name))
+}
+
+pub fn user_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("slapper"))
+}
+
+fn project_config() -> Option<PathBuf> {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| cwd.ancestors().find_map(find_in))
+}
+
+fn find_in(dir: &Path) -> Option<PathBuf> {
+    CONFIG_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+pub fn load(explicit: Option<&Path>) -> Result<LoadedConfig, ConfigError> {
+    let sources = discover(explicit);
+    if sources.is_empty() {
+        return Err(ConfigError::NotFound);
+    }
+
+    let mut merged = Value::Object(Map::new());
+    let mut includes = Vec::new();
+    for path in &sources {
+        let mut origins = IndexMap::new();
+        let value = read_with_includes(path, &mut origins, &mut Vec::new())?;
+        includes.extend(origins.into_iter().map(|(_, origin)| origin));
+        merge_values(&mut merged, value);
+    }
+    includes.retain(|path| !sources.contains(path));
+    includes.dedup();
+    let config = match serde_json::from_value(merged) {
+        Ok(config) => config,
+        Err(e) => {
+            let files: Vec<_> = sources.iter().chain(&includes).collect();
+            return Err(locate_error(&files, e));
+        }
+    };
+
+    Ok(LoadedConfig {
+        config,
+        sources,
+        includes,
+    })
+}
+
+/// Deserializes each config file on its own again for an error that points at
+/// the line and column, which the merged value no longer knows about. A file
+/// is only blamed when its own error matches the merged one, as a layer on its
+/// own may lack fields that another layer provides. Files that had to be
+/// restructured by a migration cannot be located, as their positions no longer
+/// match.
+fn locate_error(files: &[&PathBuf], error: serde_json::Error) -> ConfigError {
+    let message = error.to_string();
+    for path in files {
+        if let Some(cause) = located_error(path) {
+            if cause.to_string().contains(&message) {
+                return ConfigError::in_file(path, cause);
+            }
+        }
+    }
+    let error = ConfigError::Deserialize(failure::Error::from(error));
+    match files {
+        [path] => ConfigError::in_file(path, error),
+        _ => error,
+    }
+}
+
+fn located_error(path: &Path) -> Option<ConfigError> {
+    let content = fs::read_to_string(path).ok()?;
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::sniff(&content));
+    let value = format.deserialize::<Value>(&content).ok()?;
+    if migrate::changes_layout(&value) {
+        return None;
+    }
+    format.deserialize::<Config>(&content).err()
+}
+
+/// Reads a config file, pulling in the projects of every file it includes.
+///
+/// `origins` records which file each project came from, so that a project
+/// defined twice can be reported with both files. `stack` holds the files
+/// currently being read, to catch include cycles.
+fn read_with_includes(
+    path: &Path,
+    origins: &mut IndexMap<String, PathBuf>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Value, ConfigError> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    if stack.contains(&canonical) {
+        return Err(ConfigError::IncludeCycle {
+            path: path.display().to_string(),
+        });
+    }
+
+    let (mut value, _) = read_value(path).map_err(|cause| ConfigError::in_file(path, cause))?;
+    migrate::migrate(&mut value).map_err(|cause| ConfigError::in_file(path, cause))?;
+    let patterns: Vec<String> = match value.as_object_mut().and_then(|o| o.remove("include")) {
+        Some(include) => serde_json::from_value(include).map_err(|e| {
+            ConfigError::in_file(path, ConfigError::Deserialize(failure::Error::from(e)))
+        })?,
+        None => Vec::new(),
+    };
+
+    let mut projects = match value.as_object_mut().and_then(|o| o.remove("projects")) {
+        Some(Value::Object(projects)) => projects,
+        Some(Value::Null) | None => Map::new(),
+        Some(other) => {
+            // Leave it in place so that deserializing reports the bad type.
+            value["projects"] = other;
+            return Ok(value);
+        }
+    };
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for (name, project) in projects.iter_mut() {
+        record_origin(origins, name, path)?;
+        resolve_body_paths(project, base);
+    }
+
+    stack.push(canonical);
+    for pattern in patterns {
+        for included in expand_include(base, &pattern)? {
+            let included_value = read_with_includes(&included, origins, stack)?;
+            if let Some(Value::Object(included_projects)) = included_value.get("projects") {
+                for (name, project) in included_projects {
+                    projects.insert(name.clone(), project.clone());
+                }
+            }
+        }
+    }
+    stack.pop();
+
+    if let Some(object) = value.as_object_mut() {
+        object.insert("projects".to_owned(), Value::Object(projects));
+    }
+    Ok(value)
+}
+
+/// Makes the relative paths of file bodies in a project relative to the
+/// directory of the file that declared them, rather than the working
+/// directory.
+fn resolve_body_paths(project: &mut Value, base: &Path) {
+    let endpoints = match project.get_mut("endpoints").and_then(Value::as_object_mut) {
+        Some(endpoints) => endpoints,
+        None => return,
+    };
+    for endpoint in endpoints.values_mut() {
+        let body = match endpoint.get_mut("body") {
+            Some(body) if body.get("type").and_then(Value::as_str) == Some("File") => body,
+            _ => continue,
+        };
+        if let Some(Value::String(file)) = body.get_mut("values").and_then(|v| v.get_mut("path")) {
+            if Path::new(file.as_str()).is_relative() {
+                *file = base.join(file.as_str()).to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
+fn record_origin(
+    origins: &mut IndexMap<String, PathBuf>,
+    name: &str,
+    path: &Path,
+) -> Result<(), ConfigError> {
+    if let Some(first) = origins.get(name) {
+        return Err(ConfigError::DuplicateProject {
+            name: name.to_owned(),
+            first: first.display().to_string(),
+            second: path.display().to_string(),
+        });
+    }
+    origins.insert(name.to_owned(), path.to_owned());
+    Ok(())
+}
+
+/// Finds the files matched by an include pattern, relative to `base`.
+fn expand_include(base: &Path, pattern: &str) -> Result<Vec<PathBuf>, ConfigError> {
+    let invalid = |reason: String| ConfigError::InvalidInclude {
+        pattern: pattern.to_owned(),
+        reason,
+    };
+    let full = base.join(pattern);
+    let full = full
+        .to_str()
+        .ok_or_else(|| invalid("path is not valid UTF-8".to_owned()))?;
+
+    let mut paths = Vec::new();
+    for entry in glob::glob(full).map_err(|e| invalid(e.to_string()))? {
+        let path = entry.map_err(|e| invalid(e.to_string()))?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads a config file into a value that can be merged regardless of format.
+pub fn read_value(path: &Path) -> Result<(Value, ConfigFormat), ConfigError> {
+    let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::sniff(&content));
+    Ok((format.deserialize(&content)?, format))
+}
+
+/// Layers one value over another. Objects are merged key by key, so a layer
+/// only needs to give the fields it changes; anything else replaces the base.
+pub fn merge_values(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use super::*;
+    use crate::project::{Auth, Body};
+    use serde_json::json;
+
+    /// A fresh, empty directory under the system temp directory.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("slapper-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("services")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_include_merges_projects() {
+        let dir = scratch_dir("include");
+        fs::write(
+            dir.join("slapper.yaml"),
+            "include: [\"services/*.yaml\"]\nprojects:\n  root: {}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("services/a.yaml"), "projects:\n  a: {}\n").unwrap();
+        fs::write(dir.join("services/b.yaml"), "projects:\n  b: {}\n").unwrap();
+
+        let loaded = load(Some(&dir.join("slapper.yaml"))).unwrap();
+
+        assert_eq!(
+            loaded.config.projects.keys().collect::<Vec<_>>(),
+            vec!["root", "a", "b"]
+        );
+        assert_eq!(loaded.includes.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_duplicate_names_both_files() {
+        let dir = scratch_dir("duplicate");
+        fs::write(
+            dir.join("slapper.yaml"),
+            "include: [\"services/*.yaml\"]\nprojects:\n  a: {}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("services/a.yaml"), "projects:\n  a: {}\n").unwrap();
+
+        let message = match load(Some(&dir.join("slapper.yaml"))) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("duplicate project was accepted"),
+        };
+
+        assert!(message.contains("slapper.yaml"));
+        assert!(message.contains("a.yaml"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_adds_and_overrides() {
+        let mut base = json!({
+            "projects": {
+                "p": {
+                    "environments": { "dev": { "base_url": "http://localhost:8000/" } },
+                    "endpoints": {}
+                }
+            }
+        });
+        let layer = json!({
+            "projects": {
+                "p": { "auth": { "type": "Bearer", "values": { "token": "abc" } } },
+                "q": { "environments": {}, "endpoints": {} }
+            }
+        });
+
+        merge_values(&mut base, layer);
+
+        assert_eq!(
+            base["projects"]["p"]["environments"]["dev"]["base_url"],
+            "http://localhost:8000/"
+        );
+        assert_eq!(base["projects"]["p"]["auth"]["values"]["token"], "abc");
+        assert!(base["projects"]["q"].is_object());
+    }
+
+    #[test]
+    fn test_local_override_merges_single_fields() {
+        let dir = scratch_dir("local");
+        fs::write(
+            dir.join("slapper.json"),
+            r#"{"projects": {"p": {
+                "auth": {"type": "Basic", "values": {"username": "shared", "password": "x"}},
+                "environments": {"dev": {"base_url": "http://localhost:8000/"}}
+            }}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("slapper.local.yaml"),
+            "projects:\n  p:\n    auth:\n      values:\n        password: mine\n    environments:\n      dev:\n        base_url: http://localhost:9000/\n",
+        )
+        .unwrap();
+
+        let loaded = load(Some(&dir.join("slapper.json"))).unwrap();
+
+        assert_eq!(loaded.sources.len(), 2);
+        let project = &loaded.config.projects["p"];
+        assert_eq!(
+            project.environments["dev"].base_url.as_str(),
+            "http://localhost:9000/"
+        );
+        match &project.auth {
+            Some(Auth::Basic { username, password }) => {
+                assert_eq!(username, "shared");
+                assert_eq!(password.as_ref().unwrap(), "mine");
+            }
+            _ => panic!("expected basic auth"),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unversioned_error_located() {
+        let dir = scratch_dir("located");
+        let path = dir.join("slapper.json");
+        fs::write(
+            &path,
+            r#"{"projects":{"p":{"environments":{"dev":{"base_url":"nope"}}}}}"#,
+        )
+        .unwrap();
+
+        let message = match load(Some(&path)) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("bad URL was accepted"),
+        };
+
+        assert!(message.contains("line 1 column"), "{}", message);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_error_located_in_local_override() {
+        let dir = scratch_dir("located-local");
+        fs::write(
+            dir.join("slapper.json"),
+            r#"{"projects": {"p": {"environments": {"dev": {"base_url": "http://localhost:8000/"}}}}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("slapper.local.yaml"),
+            "projects:\n  p:\n    environments:\n      dev:\n        base_url: nope\n",
+        )
+        .unwrap();
+
+        let message = match load(Some(&dir.join("slapper.json"))) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("bad URL was accepted"),
+        };
+
+        assert!(message.contains("slapper.local.yaml"), "{}", message);
+        assert!(message.contains("line 5 column"), "{}", message);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_project_file_follows_includes() {
+        let dir = scratch_dir("owner");
+        let path = dir.join("slapper.yaml");
+        fs::write(
+            &path,
+            "include: [\"services/*.yaml\"]\nprojects:\n  main: {}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("services/a.yaml"), "projects:\n  a: {}\n").unwrap();
+
+        assert_eq!(project_file(&path, "main").unwrap(), Some(path.clone()));
+        assert_eq!(
+            project_file(&path, "a").unwrap(),
+            Some(dir.join("services").join("a.yaml"))
+        );
+        assert_eq!(project_file(&path, "b").unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_body_paths_relative_to_declaring_file() {
+        let dir = scratch_dir("body");
+        fs::write(
+            dir.join("slapper.yaml"),
+            "include: [\"services/*.yaml\"]\nprojects: {}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("services/a.yaml"),
+            "projects:\n  a:\n    endpoints:\n      e:\n        url_path: /\n        body: {type: File, values: {path: body.json}}\n",
+        )
+        .unwrap();
+
+        let loaded = load(Some(&dir.join("slapper.yaml"))).unwrap();
+
+        match &loaded.config.projects["a"].endpoints["e"].body {
+            Some(Body::File { path, .. }) => {
+                assert_eq!(Path::new(path), dir.join("services").join("body.json"))
+            }
+            other => panic!("expected a file body, got {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_replaces_non_objects() {
+        let mut base = json!({ "list": [1, 2], "value": "a" });
+
+        merge_values(
+            &mut base,
+            json!({ "list": [3], "value": { "nested": true } }),
+        );
+
+        assert_eq!(base, json!({ "list": [3], "value": { "nested": true } }));
+    }
+}
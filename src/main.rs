@@ -1,14 +1,16 @@
-mod config;
-mod hit;
-mod parse;
-mod project;
-
-use crate::config::Config;
-use crate::hit::process_hit_subcommand;
 use clap::{App, Arg, SubCommand};
-use hit::get_hit_subcommand;
-use std::fs::File;
-use std::io::Write;
+use slapper::auth::{get_auth_subcommand, process_auth_subcommand};
+use slapper::config::{self, ConfigFormat};
+use slapper::config_command::{get_config_subcommand, process_config_subcommand};
+use slapper::edit::{get_edit_subcommands, process_edit_subcommand};
+use slapper::hit::{get_hit_subcommand, process_hit_subcommand};
+use slapper::init::{get_init_subcommand, process_init_subcommand};
+use slapper::list::{get_list_subcommand, process_list_subcommand};
+use slapper::loader;
+use slapper::secret::Redactor;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
 
 fn main() {
     let matches = App::new("slapper")
@@ -23,27 +25,110 @@ fn main() {
                 .takes_value(true)
                 .help("Sets a custom config file"),
         )
+        .arg(
+            Arg::with_name("REDACT")
+                .long("redact")
+                .global(true)
+                .help("Masks secrets in printed requests and config"),
+        )
         .subcommand(get_hit_subcommand())
+        .subcommand(get_list_subcommand())
+        .subcommand(get_auth_subcommand())
+        .subcommand(get_init_subcommand())
+        .subcommands(get_edit_subcommands())
+        .subcommand(get_config_subcommand())
         .subcommand(
-            SubCommand::with_name("list").about("Lists projects, environments, and endpoints"),
+            SubCommand::with_name("write")
+                .about("Writes a config file")
+                .arg(
+                    Arg::with_name("FORMAT")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(ConfigFormat::NAMES)
+                        .help("The format to write (defaults to the file extension, or JSON)"),
+                )
+                .arg(
+                    Arg::with_name("FORCE")
+                        .long("force")
+                        .help("Overwrites the config file if it exists"),
+                ),
         )
-        .subcommand(SubCommand::with_name("write").about("Writes a config file"))
         .get_matches();
 
-    let config_file = matches.value_of("CONFIG").unwrap_or("slapper.json");
-    let config = Config::from_file(config_file).expect("could not load config");
+    let config_file = matches.value_of("CONFIG").map(Path::new);
+    let load_config = || match loader::load(config_file) {
+        Ok(loaded) => loaded.config,
+        Err(e) => {
+            eprintln!("error: could not load config: {}", e);
+            process::exit(1);
+        }
+    };
 
     match matches.subcommand() {
         ("hit", Some(matches)) => {
-            process_hit_subcommand(matches, config);
-        }
-        ("write", _) => {
-            let projects = config::get_example_config().projects;
-            let serialized = serde_json::to_string_pretty(&projects).unwrap();
-            File::create(config_file)
-                .expect("could not open file for writing")
-                .write_all(serialized.as_bytes())
-                .expect("could not write to file");
+            let mut redactor = Redactor::new(matches.is_present("REDACT"));
+            if let Err(e) = process_hit_subcommand(matches, load_config(), &mut redactor) {
+                eprintln!("error: {}", redactor.redact(&e.to_string()));
+                process::exit(e.exit_code());
+            }
+        }
+        ("auth", Some(matches)) => {
+            if let Err(e) = process_auth_subcommand(matches) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        ("init", Some(matches)) => {
+            let path = loader::primary_path(config_file);
+            if let Err(e) = process_init_subcommand(matches, &path) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        (kind @ "project", Some(matches))
+        | (kind @ "env", Some(matches))
+        | (kind @ "endpoint", Some(matches)) => {
+            if let Err(e) = process_edit_subcommand(kind, matches, config_file) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        ("config", Some(matches)) => {
+            if let Err(e) = process_config_subcommand(matches, config_file) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        ("list", Some(matches)) => {
+            process_list_subcommand(matches, load_config());
+        }
+        ("write", Some(matches)) => {
+            let format = matches
+                .value_of("FORMAT")
+                .map(|f| ConfigFormat::from_str(f).unwrap());
+            let path = match config_file {
+                Some(file) => file.to_owned(),
+                None => PathBuf::from(format!(
+                    "slapper.{}",
+                    format.unwrap_or(ConfigFormat::Json).extension()
+                )),
+            };
+            let format = format
+                .or_else(|| ConfigFormat::from_path(&path))
+                .unwrap_or(ConfigFormat::Json);
+
+            if path.exists() && !matches.is_present("FORCE") {
+                eprintln!(
+                    "error: {} already exists (use --force to overwrite)",
+                    path.display()
+                );
+                process::exit(1);
+            }
+            if let Err(e) = config::get_example_config().to_file(&path, format) {
+                eprintln!("error: could not write {}: {}", path.display(), e);
+                process::exit(1);
+            }
         }
         _ => {}
     }
@@ -0,0 +1,136 @@
+use crate::config::ConfigError;
+use serde_json::{Map, Value};
+
+/// The config layout this build reads and writes.
+pub const CURRENT_VERSION: u64 = 1;
+
+/// Upgrades from each version to the next, indexed by the version upgraded
+/// from.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[wrap_bare_projects];
+
+/// The layout version a config value was written in. Files from before the
+/// `version` key existed are version 0.
+pub fn version_of(value: &Value) -> u64 {
+    value.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Upgrades a config value to the current layout in place, returning the
+/// version it was in before.
+pub fn migrate(value: &mut Value) -> Result<u64, ConfigError> {
+    let from = version_of(value);
+    if from > CURRENT_VERSION {
+        return Err(ConfigError::UnsupportedVersion {
+            version: from,
+            supported: CURRENT_VERSION,
+        });
+    }
+    let object = match value.as_object_mut() {
+        Some(object) => object,
+        // Not a config at all; leave deserializing to say so.
+        None => return Ok(from),
+    };
+
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(object);
+    }
+    set_version(object);
+
+    Ok(from)
+}
+
+/// Whether migrating a config value would change more than its version, so
+/// that positions in the original file no longer line up with it.
+pub fn changes_layout(value: &Value) -> bool {
+    let without_version = |value: &Value| {
+        let mut value = value.clone();
+        if let Some(object) = value.as_object_mut() {
+            object.remove("version");
+        }
+        value
+    };
+
+    let mut migrated = value.clone();
+    migrate(&mut migrated).is_ok() && without_version(&migrated) != without_version(value)
+}
+
+/// Puts the version first, after any `$schema`, so that it stays at the top
+/// of the file and TOML can write it before the tables.
+fn set_version(object: &mut Map<String, Value>) {
+    let mut upgraded = Map::new();
+    let schema = object.remove("$schema");
+    object.remove("version");
+    if let Some(schema) = schema {
+        upgraded.insert("$schema".to_owned(), schema);
+    }
+    upgraded.insert("version".to_owned(), CURRENT_VERSION.into());
+    upgraded.extend(std::mem::replace(object, Map::new()));
+    *object = upgraded;
+}
+
+/// Version 0: early versions of `write` wrote the projects map on its own,
+/// without the `projects` key around it.
+fn wrap_bare_projects(object: &mut Map<String, Value>) {
+    let is_bare = !object.is_empty()
+        && !object.contains_key("projects")
+        && !object.contains_key("include")
+        && object.values().all(|project| {
+            project.as_object().is_some_and(|project| {
+                project.contains_key("environments") || project.contains_key("endpoints")
+            })
+        });
+    if is_bare {
+        let projects = std::mem::replace(object, Map::new());
+        object.insert("projects".to_owned(), Value::Object(projects));
+    }
+}
+
+#[cfg(test)]
+mod migrate_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bare_project_map_wrapped() {
+        let mut value = json!({
+            "project1": { "environments": {}, "endpoints": {} }
+        });
+
+        assert_eq!(migrate(&mut value).unwrap(), 0);
+
+        assert_eq!(
+            value,
+            json!({
+                "version": CURRENT_VERSION,
+                "projects": { "project1": { "environments": {}, "endpoints": {} } }
+            })
+        );
+    }
+
+    #[test]
+    fn test_current_untouched() {
+        let original = json!({
+            "$schema": "schema.json",
+            "version": CURRENT_VERSION,
+            "projects": {}
+        });
+        let mut value = original.clone();
+
+        assert_eq!(migrate(&mut value).unwrap(), CURRENT_VERSION);
+
+        assert_eq!(value, original);
+    }
+
+    #[test]
+    fn test_changes_layout() {
+        assert!(changes_layout(&json!({ "p": { "environments": {} } })));
+        assert!(!changes_layout(&json!({ "projects": {} })));
+        assert!(!changes_layout(&json!({ "version": 0, "projects": {} })));
+    }
+
+    #[test]
+    fn test_newer_rejected() {
+        let mut value = json!({ "version": CURRENT_VERSION + 1, "projects": {} });
+
+        assert!(migrate(&mut value).is_err());
+    }
+}
@@ -1,102 +1,327 @@
 use failure::Fail;
+use percent_encoding::{
+    define_encode_set, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET, SIMPLE_ENCODE_SET,
+};
+use regex::Regex;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::{self, Write};
-use std::iter::DoubleEndedIterator;
 use std::str::FromStr;
 
+define_encode_set! {
+    /// Everything that could change the structure of the URL a value is
+    /// substituted into, including the backslash that `Url::join` reads as a
+    /// slash and the colon that would make a leading value a scheme.
+    pub VALUE_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | {'\\', ':'}
+}
+
+define_encode_set! {
+    /// What reserved expansion still encodes: characters that may not appear
+    /// in a URL at all. Slashes, `?` and `#` pass through.
+    pub RESERVED_ENCODE_SET = [SIMPLE_ENCODE_SET] | {' ', '"', '<', '>', '`', '{', '}', '\\', '^', '|'}
+}
+
 #[derive(Debug, Clone)]
 enum SubstitutingSegment {
     Plain(String),
-    Variable(String),
+    Variable(Variable),
 }
 
+/// A `{name}` tag, which may also be `{name=default}` or `{name?}`, and may
+/// constrain its values with a type, as in `{name:int}`. Values are
+/// percent-encoded as a path segment, unless the tag is a `{+name}` reserved
+/// expansion.
 #[derive(Debug, Clone)]
-pub struct SubstitutingUrl {
-    segments: Vec<SubstitutingSegment>,
+struct Variable {
+    name: String,
+
+    /// Whether slashes and other reserved characters in values pass through.
+    reserved: bool,
+
+    kind: Option<VariableType>,
+
+    /// Used when no value is given.
+    default: Option<String>,
+
+    /// Whether the variable may be left out, taking the slash before it along.
+    optional: bool,
 }
 
-impl SubstitutingUrl {
-    fn parse(&mut self, remainder: &str) -> Result<(), ParseError> {
-        let special_chars = |x: char| x == '\\' || x == '{';
+/// The values a variable accepts.
+#[derive(Debug, Clone)]
+enum VariableType {
+    Int,
+    Uuid,
+    Pattern(Regex),
+}
 
-        if remainder.is_empty() {
-            return Ok(());
+impl VariableType {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            VariableType::Int => value.parse::<i64>().is_ok(),
+            VariableType::Uuid => is_uuid(value),
+            VariableType::Pattern(regex) => regex.is_match(value),
         }
+    }
+}
 
-        if remainder.starts_with(r"\{") {
-            let plain_start = r"\".len();
-            if let Some(pos) = &remainder[r"\{".len()..].find(special_chars) {
-                let plain_end = plain_start + pos;
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariableType::Int => f.write_str("int"),
+            VariableType::Uuid => f.write_str("uuid"),
+            VariableType::Pattern(regex) => {
+                // Strip the anchors added when compiling.
+                let source = regex.as_str();
+                let source = &source["^(?:".len()..source.len() - ")$".len()];
+                write!(f, "/{}/", source.replace('/', r"\/"))
+            }
+        }
+    }
+}
 
-                let plain = &remainder[plain_start..plain_end];
-                let remainder = &remainder[plain_end..];
+fn is_uuid(value: &str) -> bool {
+    let groups = value.split('-').map(str::len).collect::<Vec<_>>();
+    groups == [8, 4, 4, 4, 12] && value.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+}
 
-                self.segments
-                    .push(SubstitutingSegment::Plain(plain.to_owned()));
-                return self.parse(remainder);
-            }
+impl Variable {
+    /// Parses the tag starting at `offset`, which is an opening brace, and
+    /// returns it along with the offset just past its closing brace.
+    fn parse(input: &str, offset: usize) -> Result<(Self, usize), ParseError> {
+        let unterminated = ParseError::UnterminatedVariableTag { offset };
+        let mut pos = offset + "{".len();
+        let reserved = input[pos..].starts_with('+');
+        if reserved {
+            pos += "+".len();
+        }
 
-            self.segments.push(SubstitutingSegment::Plain(
-                remainder[plain_start..].to_owned(),
-            ));
-            return Ok(());
+        let name_len = input[pos..]
+            .find(|c| ":?=}".contains(c))
+            .ok_or(unterminated)?;
+        let name = &input[pos..pos + name_len];
+        if name.is_empty() {
+            return Err(ParseError::EmptyName { offset });
+        }
+        if let Some(bad) = name.find(|c: char| "{/+".contains(c) || c.is_whitespace()) {
+            return Err(ParseError::InvalidName {
+                name: name.to_owned(),
+                offset: pos + bad,
+            });
+        }
+        pos += name_len;
+
+        let mut kind = None;
+        if input[pos..].starts_with(':') {
+            pos += ":".len();
+            let (parsed, end) = Self::parse_type(input, pos, offset)?;
+            kind = Some(parsed);
+            pos = end;
         }
 
-        if remainder.starts_with('{') {
-            if let Some(pos) = remainder.find('}') {
-                let (name_start, name_end) = ("{".len(), pos);
-                let rem_start = name_end + "}".len();
+        let (default, optional) = if input[pos..].starts_with('?') {
+            pos += "?".len();
+            if !input[pos..].starts_with('}') {
+                return Err(ParseError::OptionalWithDefault { offset });
+            }
+            (None, true)
+        } else if input[pos..].starts_with('=') {
+            pos += "=".len();
+            let len = input[pos..]
+                .find('}')
+                .ok_or(ParseError::UnterminatedVariableTag { offset })?;
+            let default = input[pos..pos + len].to_owned();
+            pos += len;
+            (Some(default), false)
+        } else {
+            (None, false)
+        };
 
-                let name = &remainder[name_start..name_end];
-                if name.is_empty() {
-                    return Err(ParseError::EmptyName);
+        if !input[pos..].starts_with('}') {
+            return Err(ParseError::UnterminatedVariableTag { offset });
+        }
+        let variable = Self {
+            name: name.to_owned(),
+            reserved,
+            kind,
+            default,
+            optional,
+        };
+        Ok((variable, pos + "}".len()))
+    }
+
+    /// Parses the type after the colon at `pos`: a name, or a regex between
+    /// slashes in which `\/` stands for a slash.
+    fn parse_type(
+        input: &str,
+        pos: usize,
+        offset: usize,
+    ) -> Result<(VariableType, usize), ParseError> {
+        if input[pos..].starts_with('/') {
+            let start = pos + "/".len();
+            let mut source = String::new();
+            let mut chars = input[start..].char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '/' => {
+                        let regex = Regex::new(&format!("^(?:{})$", source)).map_err(|e| {
+                            ParseError::InvalidPattern {
+                                pattern: source.clone(),
+                                offset: pos,
+                                reason: e.to_string(),
+                            }
+                        })?;
+                        return Ok((VariableType::Pattern(regex), start + i + "/".len()));
+                    }
+                    '\\' if input[start + i..].starts_with(r"\/") => {
+                        source.push('/');
+                        chars.next();
+                    }
+                    c => source.push(c),
                 }
-                let remainder = &remainder[rem_start..];
+            }
+            return Err(ParseError::UnterminatedVariableTag { offset });
+        }
 
-                self.segments
-                    .push(SubstitutingSegment::Variable(name.to_owned()));
-                return self.parse(remainder);
+        let len = input[pos..]
+            .find(|c| "?=}".contains(c))
+            .ok_or(ParseError::UnterminatedVariableTag { offset })?;
+        let kind = match &input[pos..pos + len] {
+            "int" => VariableType::Int,
+            "uuid" => VariableType::Uuid,
+            other => {
+                return Err(ParseError::UnknownType {
+                    name: other.to_owned(),
+                    offset: pos,
+                })
             }
+        };
+        Ok((kind, pos + len))
+    }
 
-            return Err(ParseError::UnterminatedVariableTag);
+    /// Checks a value against the type, if there is one.
+    fn check(&self, value: &str) -> Result<(), SubstitutionError> {
+        match &self.kind {
+            Some(kind) if !kind.accepts(value) => Err(SubstitutionError::InvalidValue {
+                name: self.name.clone(),
+                expected: kind.to_string(),
+                value: value.to_owned(),
+            }),
+            _ => Ok(()),
         }
+    }
 
-        if let Some(pos) = remainder.find(special_chars) {
-            let (plain, remainder) = remainder.split_at(pos);
+    /// Encodes a value for the URL. Values of `.` and `..` are encoded too
+    /// outside of reserved expansion, so they cannot act as dot segments.
+    fn encode<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        if self.reserved {
+            utf8_percent_encode(value, RESERVED_ENCODE_SET).into()
+        } else if value == "." || value == ".." {
+            value.replace('.', "%2E").into()
+        } else {
+            utf8_percent_encode(value, VALUE_ENCODE_SET).into()
+        }
+    }
 
-            self.segments
-                .push(SubstitutingSegment::Plain(plain.to_owned()));
-            return self.parse(remainder);
+    fn write_repr(&self, result: &mut String) -> fmt::Result {
+        result.write_char('{')?;
+        if self.reserved {
+            result.write_char('+')?;
+        }
+        result.write_str(&self.name)?;
+        if let Some(kind) = &self.kind {
+            write!(result, ":{}", kind)?;
+        }
+        if self.optional {
+            result.write_char('?')?;
         }
+        if let Some(default) = &self.default {
+            write!(result, "={}", default)?;
+        }
+        result.write_char('}')
+    }
+}
 
-        self.segments
-            .push(SubstitutingSegment::Plain(remainder.to_owned()));
+#[derive(Debug, Clone)]
+pub struct SubstitutingUrl {
+    segments: Vec<SubstitutingSegment>,
+}
+
+impl SubstitutingUrl {
+    fn parse(input: &str) -> Result<Vec<SubstitutingSegment>, ParseError> {
+        let mut segments = Vec::new();
+        let mut plain = String::new();
+
+        let mut offset = 0;
+        while let Some(c) = input[offset..].chars().next() {
+            let remainder = &input[offset..];
+            if remainder.starts_with(r"\{") {
+                plain.push('{');
+                offset += r"\{".len();
+            } else if c == '{' {
+                let (variable, end) = Variable::parse(input, offset)?;
+                if !plain.is_empty() {
+                    segments.push(SubstitutingSegment::Plain(std::mem::take(&mut plain)));
+                }
+                segments.push(SubstitutingSegment::Variable(variable));
+                offset = end;
+            } else {
+                plain.push(c);
+                offset += c.len_utf8();
+            }
+        }
+        if !plain.is_empty() {
+            segments.push(SubstitutingSegment::Plain(plain));
+        }
+
+        Ok(segments)
+    }
+
+    /// Writes a variable's value, or its default. An optional variable with
+    /// neither is dropped along with the slash before it.
+    fn write_variable(
+        &self,
+        result: &mut String,
+        variable: &Variable,
+        value: Option<&str>,
+        position: usize,
+    ) -> Result<(), SubstitutionError> {
+        match value.or(variable.default.as_deref()) {
+            Some(value) => {
+                variable.check(value)?;
+                result.write_str(&variable.encode(value))?;
+            }
+            None if variable.optional => {
+                if result.ends_with('/') {
+                    result.pop();
+                }
+            }
+            None => {
+                return Err(SubstitutionError::MissingParameter {
+                    url: self.to_repr().into(),
+                    name: variable.name.clone(),
+                    position,
+                })
+            }
+        }
         Ok(())
     }
 
     pub fn sub_by_name(
         &self,
         values: &HashMap<String, String>,
-    ) -> Result<Cow<str>, SubstitutionError> {
-        let mut result = String::from("");
+    ) -> Result<Cow<'_, str>, SubstitutionError> {
+        let mut result = String::new();
 
         let mut position = 0usize;
         for segment in &self.segments {
             match segment {
                 SubstitutingSegment::Plain(plain) => {
-                    result.write_str(&plain)?;
+                    result.write_str(plain)?;
                 }
-                SubstitutingSegment::Variable(name) => {
-                    if let Some(val) = values.get(name) {
-                        result.write_str(&val)?;
-                    } else {
-                        return Err(SubstitutionError::MissingParameter {
-                            url: self.to_repr().into(),
-                            name: name.clone(),
-                            position,
-                        });
-                    }
+                SubstitutingSegment::Variable(variable) => {
+                    let value = values.get(&variable.name).map(String::as_str);
+                    self.write_variable(&mut result, variable, value, position)?;
                     position += 1;
                 }
             }
@@ -105,64 +330,137 @@ impl SubstitutingUrl {
         Ok(result.into())
     }
 
-    pub fn sub_by_index<I>(&self, values: I) -> Result<Cow<str>, SubstitutionError>
+    pub fn sub_by_index<I>(&self, values: I) -> Result<Cow<'_, str>, SubstitutionError>
     where
-        I: DoubleEndedIterator<Item = String>,
+        I: IntoIterator<Item = String>,
     {
         let mut result = String::new();
-        let mut values = values.rev();
+        let mut values = values.into_iter();
 
         let mut position = 0usize;
         for segment in &self.segments {
             match segment {
                 SubstitutingSegment::Plain(plain) => {
-                    result.write_str(&plain)?;
+                    result.write_str(plain)?;
                 }
-                SubstitutingSegment::Variable(name) => {
-                    let val = values
-                        .next()
-                        .ok_or_else(|| SubstitutionError::MissingParameter {
-                            url: self.to_repr().into(),
-                            name: name.clone(),
-                            position,
-                        })?;
-
-                    result.write_str(&val)?;
+                SubstitutingSegment::Variable(variable) => {
+                    let value = values.next();
+                    self.write_variable(&mut result, variable, value.as_deref(), position)?;
                     position += 1;
                 }
             }
         }
 
+        let extra = values.count();
+        if extra > 0 {
+            return Err(SubstitutionError::TooManyParameters {
+                url: self.to_repr().into(),
+                expected: position,
+                given: position + extra,
+            });
+        }
+
         Ok(result.into())
     }
 
-    pub fn to_repr(&self) -> Cow<str> {
+    /// Whether a value must be given for a variable, which is the case unless
+    /// every use of it has a default or is optional.
+    pub fn is_required(&self, name: &str) -> bool {
+        self.variables()
+            .any(|v| v.name == name && v.default.is_none() && !v.optional)
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.segments.iter().filter_map(|s| match s {
+            SubstitutingSegment::Variable(variable) => Some(variable),
+            SubstitutingSegment::Plain(_) => None,
+        })
+    }
+
+    /// The names of the variables in the template, in order of appearance.
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables().map(|v| v.name.as_str())
+    }
+
+    pub fn to_repr(&self) -> Cow<'_, str> {
         let mut result = String::new();
         for s in &self.segments {
             match s {
                 SubstitutingSegment::Plain(text) => result
-                    .write_str(&text)
+                    .write_str(&text.replace('{', r"\{"))
                     .expect("unknown error when writing plain repr"),
-                SubstitutingSegment::Variable(name) => {
-                    write!(&mut result, "{{{}}}", &name).expect("unknown error when writing name")
-                }
+                SubstitutingSegment::Variable(variable) => variable
+                    .write_repr(&mut result)
+                    .expect("unknown error when writing name"),
             }
         }
         result.into()
     }
 }
 
+impl fmt::Display for SubstitutingUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_repr())
+    }
+}
+
 impl FromStr for SubstitutingUrl {
     type Err = ParseError;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut subber = SubstitutingUrl {
-            segments: Vec::new(),
-        };
-        subber.parse(input)?;
+        Ok(SubstitutingUrl {
+            segments: Self::parse(input)?,
+        })
+    }
+}
+
+/// Finds the `{name}` tags in free text, such as headers and bodies. Braces
+/// around anything but a plain name are not tags, so JSON passes through.
+fn template_tags(text: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        while let Some(start) = text[offset..].find('{').map(|pos| offset + pos) {
+            let end = match text[start..].find('}') {
+                Some(pos) => start + pos,
+                None => return None,
+            };
+            let name = &text[start + "{".len()..end];
+            let is_name = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+            if is_name {
+                offset = end + "}".len();
+                return Some((start, offset, name));
+            }
+            offset = start + "{".len();
+        }
+        None
+    })
+}
+
+/// The names of the tags in free text.
+pub fn template_names(text: &str) -> impl Iterator<Item = &str> {
+    template_tags(text).map(|(_, _, name)| name)
+}
 
-        Ok(subber)
+/// Fills the tags in free text that have a value, leaving the rest as they
+/// are.
+pub fn substitute_template<'a>(text: &'a str, values: &HashMap<String, String>) -> Cow<'a, str> {
+    let mut result = String::new();
+    let mut copied = 0;
+    for (start, end, name) in template_tags(text) {
+        if let Some(value) = values.get(name) {
+            result.push_str(&text[copied..start]);
+            result.push_str(value);
+            copied = end;
+        }
     }
+    if copied == 0 {
+        return text.into();
+    }
+    result.push_str(&text[copied..]);
+    result.into()
 }
 
 #[cfg(test)]
@@ -200,14 +498,335 @@ mod url_replace_tests {
         let result = subber.sub_by_name(&test_values).unwrap();
         assert_eq!(result, "la/dedah".to_owned());
     }
+
+    #[test]
+    fn test_index_plain() {
+        let subber = SubstitutingUrl::from_str("test/something/blah").unwrap();
+
+        let result = subber.sub_by_index(Vec::new()).unwrap();
+        assert_eq!(result, "test/something/blah".to_owned());
+    }
+
+    #[test]
+    fn test_index_parse() {
+        let subber = SubstitutingUrl::from_str("test/{val}/blah").unwrap();
+
+        let result = subber.sub_by_index(vec!["something".to_owned()]).unwrap();
+        assert_eq!(result, "test/something/blah".to_owned());
+    }
+
+    #[test]
+    fn test_index_multi_parse_in_order() {
+        let subber = SubstitutingUrl::from_str("{v1}/{v2}{v3}").unwrap();
+
+        let test_values = vec!["la".to_owned(), "de".to_owned(), "dah".to_owned()];
+
+        let result = subber.sub_by_index(test_values).unwrap();
+        assert_eq!(result, "la/dedah".to_owned());
+    }
+
+    #[test]
+    fn test_index_too_few() {
+        let subber = SubstitutingUrl::from_str("{v1}/{v2}").unwrap();
+
+        match subber.sub_by_index(vec!["la".to_owned()]) {
+            Err(SubstitutionError::MissingParameter { name, position, .. }) => {
+                assert_eq!(name, "v2");
+                assert_eq!(position, 1);
+            }
+            other => panic!("expected missing parameter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_index_too_many() {
+        let subber = SubstitutingUrl::from_str("{v1}/{v2}").unwrap();
+
+        let test_values = vec!["la".to_owned(), "de".to_owned(), "dah".to_owned()];
+
+        match subber.sub_by_index(test_values) {
+            Err(SubstitutionError::TooManyParameters {
+                expected, given, ..
+            }) => {
+                assert_eq!(expected, 2);
+                assert_eq!(given, 3);
+            }
+            other => panic!("expected too many parameters, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_default_used_when_unset() {
+        let subber = SubstitutingUrl::from_str("items/{page=1}").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "items/1");
+        assert_eq!(
+            subber.sub_by_index(vec!["3".to_owned()]).unwrap(),
+            "items/3"
+        );
+        assert!(!subber.is_required("page"));
+    }
+
+    #[test]
+    fn test_empty_default() {
+        let subber = SubstitutingUrl::from_str("a{x=}b").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_optional_drops_preceding_slash() {
+        let subber = SubstitutingUrl::from_str("orders/{id?}/lines").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "orders/lines");
+        assert_eq!(
+            subber.sub_by_index(vec!["7".to_owned()]).unwrap(),
+            "orders/7/lines"
+        );
+    }
+
+    #[test]
+    fn test_optional_at_end_and_start() {
+        let subber = SubstitutingUrl::from_str("{a?}/orders/{b?}").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "/orders");
+    }
+
+    #[test]
+    fn test_escaped_brace_round_trips() {
+        let subber = SubstitutingUrl::from_str(r"a/\{literal}/{v=x}/{o?}").unwrap();
+
+        assert_eq!(subber.variable_names().collect::<Vec<_>>(), vec!["v", "o"]);
+        assert_eq!(subber.to_repr(), r"a/\{literal}/{v=x}/{o?}");
+        assert_eq!(
+            subber.sub_by_name(&HashMap::new()).unwrap(),
+            "a/{literal}/x"
+        );
+    }
+
+    #[test]
+    fn test_lone_backslash_is_plain() {
+        let subber = SubstitutingUrl::from_str(r"a\b/{v}").unwrap();
+
+        assert_eq!(subber.sub_by_index(vec!["c".to_owned()]).unwrap(), r"a\b/c");
+    }
+
+    #[test]
+    fn test_error_offsets() {
+        match SubstitutingUrl::from_str("abc/{}") {
+            Err(ParseError::EmptyName { offset }) => assert_eq!(offset, 4),
+            other => panic!("expected empty name, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("ab/{x}/{y") {
+            Err(ParseError::UnterminatedVariableTag { offset }) => assert_eq!(offset, 7),
+            other => panic!("expected unterminated tag, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("ab/{x{y}") {
+            Err(ParseError::InvalidName { offset, .. }) => assert_eq!(offset, 5),
+            other => panic!("expected invalid name, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("{x?=1}") {
+            Err(ParseError::OptionalWithDefault { offset }) => assert_eq!(offset, 0),
+            other => panic!("expected optional with default, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("{?}") {
+            Err(ParseError::EmptyName { offset }) => assert_eq!(offset, 0),
+            other => panic!("expected empty name, got {:?}", other),
+        }
+    }
+
+    fn assert_invalid_value(result: Result<Cow<'_, str>, SubstitutionError>, expected_type: &str) {
+        match result {
+            Err(SubstitutionError::InvalidValue { expected, .. }) => {
+                assert_eq!(expected, expected_type)
+            }
+            other => panic!("expected invalid value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_typed_int() {
+        let subber = SubstitutingUrl::from_str("orders/{id:int}").unwrap();
+
+        assert_eq!(
+            subber.sub_by_index(vec!["-42".to_owned()]).unwrap(),
+            "orders/-42"
+        );
+        assert_invalid_value(subber.sub_by_index(vec!["4x2".to_owned()]), "int");
+    }
+
+    #[test]
+    fn test_typed_uuid() {
+        let subber = SubstitutingUrl::from_str("{id:uuid}").unwrap();
+        let uuid = "123e4567-E89B-12d3-a456-426614174000".to_owned();
+
+        assert_eq!(subber.sub_by_index(vec![uuid.clone()]).unwrap(), uuid);
+        assert_invalid_value(
+            subber.sub_by_index(vec!["123e4567-e89b-12d3-a456-42661417400".to_owned()]),
+            "uuid",
+        );
+    }
+
+    #[test]
+    fn test_typed_pattern_with_braces_and_slashes() {
+        let subber = SubstitutingUrl::from_str(r"x/{code:/[a-z]{2}\/\d+/}/y").unwrap();
+
+        assert_eq!(
+            subber.sub_by_index(vec!["ab/12".to_owned()]).unwrap(),
+            "x/ab%2F12/y"
+        );
+        assert_invalid_value(
+            subber.sub_by_index(vec!["abc/12".to_owned()]),
+            r"/[a-z]{2}\/\d+/",
+        );
+        assert_eq!(subber.to_repr(), r"x/{code:/[a-z]{2}\/\d+/}/y");
+    }
+
+    #[test]
+    fn test_typed_with_default_and_optional() {
+        let subber = SubstitutingUrl::from_str("a/{page:int=1}/{id:int?}").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "a/1");
+        assert_eq!(subber.to_repr(), "a/{page:int=1}/{id:int?}");
+
+        let subber = SubstitutingUrl::from_str("{page:int=one}").unwrap();
+        assert_invalid_value(subber.sub_by_name(&HashMap::new()), "int");
+    }
+
+    #[test]
+    fn test_type_errors() {
+        match SubstitutingUrl::from_str("ab/{x:float}") {
+            Err(ParseError::UnknownType { name, offset }) => {
+                assert_eq!(name, "float");
+                assert_eq!(offset, 6);
+            }
+            other => panic!("expected unknown type, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("{x:/[a-/}") {
+            Err(ParseError::InvalidPattern { offset, .. }) => assert_eq!(offset, 3),
+            other => panic!("expected invalid pattern, got {:?}", other),
+        }
+        match SubstitutingUrl::from_str("{x:/abc}") {
+            Err(ParseError::UnterminatedVariableTag { offset }) => assert_eq!(offset, 0),
+            other => panic!("expected unterminated tag, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_values_encoded_as_segment() {
+        let subber = SubstitutingUrl::from_str("files/{name}/meta").unwrap();
+
+        let result = subber
+            .sub_by_index(vec![r"a/b?c#d e%f\g".to_owned()])
+            .unwrap();
+        assert_eq!(result, "files/a%2Fb%3Fc%23d%20e%25f%5Cg/meta");
+    }
+
+    #[test]
+    fn test_colon_values_keep_base() {
+        let subber = SubstitutingUrl::from_str("{id}/orders").unwrap();
+
+        let result = subber.sub_by_index(vec!["urn:1".to_owned()]).unwrap();
+        assert_eq!(result, "urn%3A1/orders");
+        let base = url::Url::parse("http://h/api/").unwrap();
+        assert_eq!(
+            base.join(&result).unwrap().as_str(),
+            "http://h/api/urn%3A1/orders"
+        );
+    }
+
+    #[test]
+    fn test_dot_values_encoded() {
+        let subber = SubstitutingUrl::from_str("files/{name}/meta").unwrap();
+
+        let result = subber.sub_by_index(vec!["..".to_owned()]).unwrap();
+        assert_eq!(result, "files/%2E%2E/meta");
+        let result = subber.sub_by_index(vec![".".to_owned()]).unwrap();
+        assert_eq!(result, "files/%2E/meta");
+        let result = subber.sub_by_index(vec!["a..b".to_owned()]).unwrap();
+        assert_eq!(result, "files/a..b/meta");
+    }
+
+    #[test]
+    fn test_values_encoded_as_utf8() {
+        let subber = SubstitutingUrl::from_str("cities/{name}").unwrap();
+
+        let result = subber.sub_by_index(vec!["São Paulo".to_owned()]).unwrap();
+        assert_eq!(result, "cities/S%C3%A3o%20Paulo");
+    }
+
+    #[test]
+    fn test_reserved_expansion_keeps_slashes() {
+        let subber = SubstitutingUrl::from_str("repo/{+path}").unwrap();
+
+        let result = subber
+            .sub_by_index(vec!["src/main.rs?raw=1#L2".to_owned()])
+            .unwrap();
+        assert_eq!(result, "repo/src/main.rs?raw=1#L2");
+        let result = subber.sub_by_index(vec!["a b/c".to_owned()]).unwrap();
+        assert_eq!(result, "repo/a%20b/c");
+        assert_eq!(subber.to_repr(), "repo/{+path}");
+    }
+
+    #[test]
+    fn test_reserved_expansion_with_type_and_default() {
+        let subber = SubstitutingUrl::from_str(r"{+path:/[a-z\/]+/=docs/index}").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "docs/index");
+        match SubstitutingUrl::from_str("{a+b}") {
+            Err(ParseError::InvalidName { offset, .. }) => assert_eq!(offset, 2),
+            other => panic!("expected invalid name, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_defaults_encoded() {
+        let subber = SubstitutingUrl::from_str("q/{term=a b}").unwrap();
+
+        assert_eq!(subber.sub_by_name(&HashMap::new()).unwrap(), "q/a%20b");
+    }
+
+    #[test]
+    fn test_template_names_skip_json() {
+        let text = r#"{"tenant": "{tenant_id}", "items": [{ "id": 1 }]}"#;
+
+        assert_eq!(template_names(text).collect::<Vec<_>>(), vec!["tenant_id"]);
+    }
+
+    #[test]
+    fn test_template_leaves_unknown() {
+        let mut values = HashMap::new();
+        values.insert("tenant_id".to_owned(), "acme".to_owned());
+
+        let result = substitute_template(r#"{"t": "{tenant_id}", "o": "{other}"}"#, &values);
+        assert_eq!(result, r#"{"t": "acme", "o": "{other}"}"#);
+    }
 }
 
 #[derive(Debug, Fail)]
 pub enum ParseError {
-    #[fail(display = "parsed tag name empty")]
-    EmptyName,
-    #[fail(display = "parsed tag unterminated")]
-    UnterminatedVariableTag,
+    #[fail(display = "parsed tag name empty at byte {}", offset)]
+    EmptyName { offset: usize },
+    #[fail(display = "parsed tag unterminated at byte {}", offset)]
+    UnterminatedVariableTag { offset: usize },
+    #[fail(
+        display = "invalid character in tag name '{}' at byte {}",
+        name, offset
+    )]
+    InvalidName { name: String, offset: usize },
+    #[fail(display = "optional tag with a default at byte {}", offset)]
+    OptionalWithDefault { offset: usize },
+    #[fail(display = "unknown tag type '{}' at byte {}", name, offset)]
+    UnknownType { name: String, offset: usize },
+    #[fail(
+        display = "invalid tag pattern /{}/ at byte {}: {}",
+        pattern, offset, reason
+    )]
+    InvalidPattern {
+        pattern: String,
+        offset: usize,
+        reason: String,
+    },
 }
 
 #[derive(Debug, Fail)]
@@ -218,6 +837,21 @@ pub enum SubstitutionError {
         name: String,
         position: usize,
     },
+    #[fail(display = "expected {} parameters but got {}", expected, given)]
+    TooManyParameters {
+        url: String,
+        expected: usize,
+        given: usize,
+    },
+    #[fail(
+        display = "value '{}' for {{{}}} is not a valid {}",
+        value, name, expected
+    )]
+    InvalidValue {
+        name: String,
+        expected: String,
+        value: String,
+    },
     #[fail(display = "{}", _0)]
     WriteError(#[fail(cause)] fmt::Error),
 }
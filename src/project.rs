@@ -1,10 +1,14 @@
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use url::Url;
 
-pub type ProjectMap = HashMap<String, Project>;
+pub type ProjectMap = IndexMap<String, Project>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Header or query parameter names and their values, in the order given.
+pub type ValueMap = IndexMap<String, String>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", content = "values")]
 pub enum Auth {
     Inherit,
@@ -17,14 +21,74 @@ pub enum Auth {
 
         grant_type: String,
 
-        resource: String,
+        /// The Azure AD v1 `resource` to request a token for.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        resource: Option<String>,
 
+        /// Space-separated OAuth2 scopes, sent as `scope`.
         #[serde(skip_serializing_if = "Option::is_none")]
         scopes: Option<String>,
+
+        #[serde(default, skip_serializing_if = "ClientAuthentication::is_default")]
+        client_authentication: ClientAuthentication,
+    },
+    Basic {
+        username: String,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+    ApiKey {
+        name: String,
+
+        value: String,
+
+        #[serde(default, skip_serializing_if = "ApiKeyLocation::is_default")]
+        location: ApiKeyLocation,
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where an API key is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+impl ApiKeyLocation {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Auth {
+    pub fn is_inherit(&self) -> bool {
+        matches!(self, Auth::Inherit)
+    }
+}
+
+/// How the client proves its identity to the token endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ClientAuthentication {
+    /// `client_id` and `client_secret` as form fields.
+    #[default]
+    Body,
+
+    /// `client_id` and `client_secret` in an HTTP Basic header.
+    Basic,
+}
+
+impl ClientAuthentication {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Endpoint {
     pub url_path: String,
 
@@ -33,6 +97,18 @@ pub struct Endpoint {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth: Option<Auth>,
+
+    /// Headers sent with every request, over the environment's and project's.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub headers: ValueMap,
+
+    /// Query parameters sent with every request.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub query: ValueMap,
+
+    /// The body sent when none is given on the command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
 }
 
 impl Endpoint {
@@ -41,10 +117,40 @@ impl Endpoint {
             url_path: url_path.to_owned(),
             method: method_default(),
             auth: Some(Auth::Inherit),
+            headers: ValueMap::new(),
+            query: ValueMap::new(),
+            body: None,
         }
     }
 }
 
+/// A request body kept in the config.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", content = "values")]
+pub enum Body {
+    /// Text sent as is, or anything else sent as JSON.
+    Inline {
+        // Before `content`, as TOML can't have a value after a table.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        media: Option<String>,
+
+        content: serde_json::Value,
+    },
+    /// The contents of a file, relative to the config file that declares it.
+    File {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        media: Option<String>,
+
+        path: String,
+    },
+}
+
+/// Sets a header, replacing any with the same name regardless of case.
+pub fn set_header(headers: &mut ValueMap, name: &str, value: &str) {
+    headers.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+    headers.insert(name.to_owned(), value.to_owned());
+}
+
 fn method_default() -> String {
     "GET".into()
 }
@@ -53,13 +159,21 @@ fn skip_if_get(value: &str) -> bool {
     value.is_empty() || value.to_uppercase() == "GET"
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Environment {
     #[serde(with = "url_serde")]
+    #[schemars(with = "String", url)]
     pub base_url: Url,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth: Option<Auth>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub headers: ValueMap,
+
+    /// Values for path, header and body variables, over the project's.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variables: ValueMap,
 }
 
 impl Environment {
@@ -67,6 +181,8 @@ impl Environment {
         Self {
             base_url,
             auth: Some(Auth::Inherit),
+            headers: ValueMap::new(),
+            variables: ValueMap::new(),
         }
     }
 
@@ -74,18 +190,29 @@ impl Environment {
         Self {
             base_url,
             auth: Some(auth),
+            headers: ValueMap::new(),
+            variables: ValueMap::new(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Project {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth: Option<Auth>,
 
-    pub environments: HashMap<String, Environment>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub headers: ValueMap,
+
+    /// Values for path, header and body variables in every environment.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variables: ValueMap,
 
-    pub endpoints: HashMap<String, Endpoint>,
+    #[serde(default)]
+    pub environments: IndexMap<String, Environment>,
+
+    #[serde(default)]
+    pub endpoints: IndexMap<String, Endpoint>,
 }
 
 impl Project {
@@ -93,6 +220,69 @@ impl Project {
         Self::default()
     }
 
+    /// The auth for an environment, following `Auth::Inherit` to the project.
+    pub fn environment_auth<'a>(&'a self, environment: &'a Environment) -> Option<&'a Auth> {
+        match environment.auth {
+            Some(Auth::Inherit) => self.auth.as_ref().filter(|a| !a.is_inherit()),
+            _ => environment.auth.as_ref(),
+        }
+    }
+
+    /// The auth for an endpoint in an environment, following `Auth::Inherit`
+    /// through the environment to the project.
+    pub fn endpoint_auth<'a>(
+        &'a self,
+        environment: &'a Environment,
+        endpoint: &'a Endpoint,
+    ) -> Option<&'a Auth> {
+        match endpoint.auth {
+            Some(Auth::Inherit) => self.environment_auth(environment),
+            _ => endpoint.auth.as_ref(),
+        }
+    }
+
+    /// The auth for a new environment: `Inherit`, unless the project has no
+    /// auth for it to inherit.
+    pub fn new_environment_auth(&self) -> Option<Auth> {
+        self.auth.as_ref().map(|_| Auth::Inherit)
+    }
+
+    /// The auth for a new endpoint: `Inherit`, unless none of the project's
+    /// environments has auth for it to inherit.
+    pub fn new_endpoint_auth(&self) -> Option<Auth> {
+        let nothing_to_inherit = !self.environments.is_empty()
+            && self
+                .environments
+                .values()
+                .all(|environment| self.environment_auth(environment).is_none());
+        if nothing_to_inherit {
+            None
+        } else {
+            Some(Auth::Inherit)
+        }
+    }
+
+    /// The headers for a request, merged down the same way as auth: the
+    /// environment's override the project's and the endpoint's override both.
+    pub fn request_headers(
+        &self,
+        environment: &Environment,
+        endpoint: Option<&Endpoint>,
+    ) -> ValueMap {
+        let mut headers = ValueMap::new();
+        let layers = [
+            Some(&self.headers),
+            Some(&environment.headers),
+            endpoint.map(|e| &e.headers),
+        ];
+        for layer in layers.iter().flatten() {
+            for (name, value) in layer.iter() {
+                set_header(&mut headers, name, value);
+            }
+        }
+        headers
+    }
+
     pub fn from_full<IEnvironment, IEndpoint>(
         auth: Option<Auth>,
         environments: IEnvironment,
@@ -102,30 +292,12 @@ impl Project {
         IEnvironment: IntoIterator<Item = (String, Environment)>,
         IEndpoint: IntoIterator<Item = (String, Endpoint)>,
     {
-        let mut env_map = HashMap::new();
-        for (k, v) in environments {
-            env_map.insert(k, v);
-        }
-
-        let mut end_map = HashMap::new();
-        for (k, v) in endpoints {
-            end_map.insert(k, v);
-        }
-
         Self {
             auth,
-            environments: env_map,
-            endpoints: end_map,
-        }
-    }
-}
-
-impl Default for Project {
-    fn default() -> Self {
-        Self {
-            auth: None,
-            environments: HashMap::new(),
-            endpoints: HashMap::new(),
+            headers: ValueMap::new(),
+            variables: ValueMap::new(),
+            environments: environments.into_iter().collect(),
+            endpoints: endpoints.into_iter().collect(),
         }
     }
 }
@@ -172,3 +344,30 @@ mod url_serde {
         deserializer.deserialize_str(UrlVisitor)
     }
 }
+
+#[cfg(test)]
+mod project_tests {
+    use super::*;
+
+    #[test]
+    fn test_request_headers_merge_down() {
+        let mut project = Project::new();
+        set_header(&mut project.headers, "Accept", "text/plain");
+        set_header(&mut project.headers, "X-Team", "core");
+        let mut environment = Environment::new(Url::parse("http://localhost:8000").unwrap());
+        set_header(&mut environment.headers, "x-team", "qa");
+        let mut endpoint = Endpoint::new("/");
+        set_header(&mut endpoint.headers, "accept", "application/json");
+
+        let headers = project.request_headers(&environment, Some(&endpoint));
+
+        assert_eq!(
+            headers.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("x-team".to_owned(), "qa".to_owned()),
+                ("accept".to_owned(), "application/json".to_owned()),
+            ]
+        );
+        assert_eq!(project.request_headers(&environment, None).len(), 2);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::parse::VALUE_ENCODE_SET;
+use crate::project::Auth;
+use failure::Fail;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io;
+use std::process::{Command, ExitStatus};
+use url::form_urlencoded;
+use url::percent_encoding::{utf8_percent_encode, QUERY_ENCODE_SET};
+
+const REDACTED: &str = "****";
+
+/// Resolves the `${env:NAME}`, `${file:/path}` and `${cmd:command}`
+/// references in a string. Text outside references is kept as is.
+pub fn resolve(input: &str) -> Result<Cow<'_, str>, SecretError> {
+    if !input.contains("${") {
+        return Ok(input.into());
+    }
+
+    let mut result = String::new();
+    let mut remainder = input;
+    while let Some(start) = remainder.find("${") {
+        result.push_str(&remainder[..start]);
+        let reference = &remainder[start + "${".len()..];
+        let end = reference
+            .find('}')
+            .ok_or_else(|| SecretError::Unterminated(input.to_owned()))?;
+        result.push_str(&resolve_reference(&reference[..end])?);
+        remainder = &reference[end + "}".len()..];
+    }
+    result.push_str(remainder);
+
+    Ok(result.into())
+}
+
+fn resolve_reference(reference: &str) -> Result<String, SecretError> {
+    let (source, value) = match reference.find(':') {
+        Some(pos) => (&reference[..pos], &reference[pos + 1..]),
+        None => return Err(SecretError::UnknownSource(reference.to_owned())),
+    };
+
+    match source {
+        "env" => env::var(value).map_err(|_| SecretError::MissingEnv(value.to_owned())),
+        "file" => fs::read_to_string(value)
+            .map(|content| content.trim_end_matches(&['\r', '\n'][..]).to_owned())
+            .map_err(|cause| SecretError::File {
+                path: value.to_owned(),
+                cause,
+            }),
+        "cmd" => run_command(value),
+        _ => Err(SecretError::UnknownSource(source.to_owned())),
+    }
+}
+
+fn run_command(command: &str) -> Result<String, SecretError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|cause| SecretError::CommandIo {
+        command: command.to_owned(),
+        cause,
+    })?;
+
+    if !output.status.success() {
+        return Err(SecretError::CommandFailed {
+            command: command.to_owned(),
+            status: output.status,
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(&['\r', '\n'][..])
+        .to_owned())
+}
+
+/// Returns a copy of the auth with the references in every string field
+/// resolved. Secret fields are added to the redactor.
+pub fn resolve_auth(auth: &Auth, redactor: &mut Redactor) -> Result<Auth, SecretError> {
+    let plain = |value: &str| resolve(value).map(Cow::into_owned);
+
+    Ok(match auth {
+        Auth::Inherit => Auth::Inherit,
+        Auth::ClientCredentials {
+            authority,
+            client_id,
+            client_secret,
+            grant_type,
+            resource,
+            scopes,
+            client_authentication,
+        } => Auth::ClientCredentials {
+            authority: plain(authority)?,
+            client_id: plain(client_id)?,
+            client_secret: redactor.secret(plain(client_secret)?),
+            grant_type: plain(grant_type)?,
+            resource: resource.as_ref().map(|r| plain(r)).transpose()?,
+            scopes: scopes.as_ref().map(|s| plain(s)).transpose()?,
+            client_authentication: *client_authentication,
+        },
+        Auth::Basic { username, password } => Auth::Basic {
+            username: plain(username)?,
+            password: password
+                .as_ref()
+                .map(|p| plain(p).map(|p| redactor.secret(p)))
+                .transpose()?,
+        },
+        Auth::Bearer { token } => Auth::Bearer {
+            token: redactor.secret(plain(token)?),
+        },
+        Auth::ApiKey {
+            name,
+            value,
+            location,
+        } => Auth::ApiKey {
+            name: plain(name)?,
+            value: redactor.secret(plain(value)?),
+            location: *location,
+        },
+    })
+}
+
+/// Returns a copy of the auth with its secret fields masked, for printing.
+pub fn mask_auth(auth: &Auth) -> Auth {
+    let mut masked = auth.clone();
+    match &mut masked {
+        Auth::ClientCredentials { client_secret, .. } => *client_secret = REDACTED.to_owned(),
+        Auth::Basic {
+            password: Some(password),
+            ..
+        } => *password = REDACTED.to_owned(),
+        Auth::Bearer { token } => *token = REDACTED.to_owned(),
+        Auth::ApiKey { value, .. } => *value = REDACTED.to_owned(),
+        Auth::Basic { password: None, .. } | Auth::Inherit => {}
+    }
+    masked
+}
+
+/// Masks known secret values in text that is about to be printed.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    enabled: bool,
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            secrets: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Remembers a secret value and hands it back. The forms it takes once
+    /// encoded into a URL are remembered too, so that it is also found there.
+    pub fn secret(&mut self, value: String) -> String {
+        if value.is_empty() {
+            return value;
+        }
+
+        let forms = [
+            value.clone(),
+            form_urlencoded::byte_serialize(value.as_bytes()).collect(),
+            utf8_percent_encode(&value, QUERY_ENCODE_SET).to_string(),
+            utf8_percent_encode(&value, VALUE_ENCODE_SET).to_string(),
+        ];
+        for form in forms.iter() {
+            if !self.secrets.contains(form) {
+                self.secrets.push(form.clone());
+            }
+        }
+        // Longer forms first, so that a form containing another is not left
+        // half replaced.
+        self.secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        value
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.enabled || !self.secrets.iter().any(|s| text.contains(s.as_str())) {
+            return text.into();
+        }
+
+        let mut result = text.to_owned();
+        for secret in &self.secrets {
+            result = result.replace(secret.as_str(), REDACTED);
+        }
+        result.into()
+    }
+
+    /// Masks a whole value regardless of whether it is a known secret.
+    pub fn mask<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.enabled {
+            REDACTED.into()
+        } else {
+            text.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod secret_tests {
+    use super::*;
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(resolve("no references").unwrap(), "no references");
+    }
+
+    #[test]
+    fn test_env() {
+        env::set_var("SLAPPER_TEST_SECRET", "hunter2");
+
+        let result = resolve("Bearer ${env:SLAPPER_TEST_SECRET}!").unwrap();
+        assert_eq!(result, "Bearer hunter2!");
+    }
+
+    #[test]
+    fn test_cmd() {
+        if cfg!(unix) {
+            assert_eq!(resolve("${cmd:echo hello}").unwrap(), "hello");
+        }
+    }
+
+    #[test]
+    fn test_unknown_source() {
+        match resolve("${vault:x}") {
+            Err(SecretError::UnknownSource(source)) => assert_eq!(source, "vault"),
+            other => panic!("expected unknown source, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unterminated() {
+        match resolve("${env:X") {
+            Err(SecretError::Unterminated(_)) => {}
+            other => panic!("expected unterminated reference, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut redactor = Redactor::new(true);
+        redactor.secret("hunter2".to_owned());
+
+        assert_eq!(redactor.redact("key=hunter2&x=1"), "key=****&x=1");
+    }
+
+    #[test]
+    fn test_redact_encoded() {
+        let mut redactor = Redactor::new(true);
+        redactor.secret("ab+c/d== e".to_owned());
+
+        assert_eq!(
+            redactor.redact("http://x/?page=1&key=ab%2Bc%2Fd%3D%3D+e"),
+            "http://x/?page=1&key=****"
+        );
+        assert_eq!(
+            redactor.redact("http://x/ab+c%2Fd==%20e/"),
+            "http://x/****/"
+        );
+        assert_eq!(redactor.redact("raw ab+c/d== e"), "raw ****");
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum SecretError {
+    #[fail(display = "unterminated secret reference in '{}'", _0)]
+    Unterminated(String),
+
+    #[fail(display = "unknown secret source '{}'", _0)]
+    UnknownSource(String),
+
+    #[fail(display = "environment variable {} is not set", _0)]
+    MissingEnv(String),
+
+    #[fail(display = "could not read secret file {}: {}", path, cause)]
+    File {
+        path: String,
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
+    #[fail(display = "could not run secret command '{}': {}", command, cause)]
+    CommandIo {
+        command: String,
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
+    #[fail(display = "secret command '{}' failed with {}", command, status)]
+    CommandFailed { command: String, status: ExitStatus },
+}
@@ -0,0 +1,149 @@
+use crate::config::Config;
+use crate::hit::validate_method;
+use crate::parse::SubstitutingUrl;
+use crate::project::{Auth, Project};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Likely a mistake, but the config still works.
+    Warning,
+    Error,
+}
+
+/// A problem found in a config, with where it was found.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Checks what deserializing cannot: that endpoint paths and methods are
+/// valid and that inherited auth has something to inherit.
+pub fn validate(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (name, project) in &config.projects {
+        validate_project(name, project, &mut diagnostics);
+    }
+    diagnostics
+}
+
+fn validate_project(name: &str, project: &Project, diagnostics: &mut Vec<Diagnostic>) {
+    let mut report = |severity, location: String, message: String| {
+        diagnostics.push(Diagnostic {
+            severity,
+            location,
+            message,
+        })
+    };
+    let project_location = format!("project '{}'", name);
+
+    if let Some(Auth::Inherit) = project.auth {
+        report(
+            Severity::Error,
+            project_location.clone(),
+            "auth is Inherit, but a project has nothing to inherit from".to_owned(),
+        );
+    }
+
+    for (env_name, environment) in &project.environments {
+        if let (Some(Auth::Inherit), None) = (&environment.auth, &project.auth) {
+            report(
+                Severity::Error,
+                format!("{}, environment '{}'", project_location, env_name),
+                "inherits auth, but the project has none".to_owned(),
+            );
+        }
+    }
+
+    for (endpoint_name, endpoint) in &project.endpoints {
+        let location = format!("{}, endpoint '{}'", project_location, endpoint_name);
+
+        if let Err(e) = SubstitutingUrl::from_str(&endpoint.url_path) {
+            report(
+                Severity::Error,
+                location.clone(),
+                format!("invalid url_path '{}': {}", endpoint.url_path, e),
+            );
+        }
+        if let Err(e) = validate_method(endpoint.method.clone()) {
+            report(Severity::Error, location.clone(), e);
+        }
+        if let Some(Auth::Inherit) = endpoint.auth {
+            for (env_name, environment) in &project.environments {
+                if environment.auth.is_none() {
+                    report(
+                        Severity::Error,
+                        location.clone(),
+                        format!("inherits auth, but environment '{}' has none", env_name),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::config::ConfigFormat;
+
+    fn diagnostics_for(yaml: &str) -> Vec<String> {
+        let config = ConfigFormat::Yaml.deserialize::<Config>(yaml).unwrap();
+        validate(&config).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        let diagnostics = diagnostics_for(
+            "projects:\n  p:\n    auth: { type: Bearer, values: { token: t } }\n    environments:\n      dev: { base_url: 'http://localhost/', auth: { type: Inherit } }\n    endpoints:\n      e: { url_path: '/{id}', method: post, auth: { type: Inherit } }\n",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_example_config_valid() {
+        let diagnostics = validate(&crate::config::get_example_config());
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_bad_path_and_method() {
+        let diagnostics = diagnostics_for(
+            "projects:\n  p:\n    endpoints:\n      e: { url_path: '/{id', method: 'NOT A METHOD' }\n",
+        );
+
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].starts_with("error: project 'p', endpoint 'e': invalid url_path"));
+        assert!(diagnostics[1].contains("invalid HTTP method"));
+    }
+
+    #[test]
+    fn test_dangling_inherit() {
+        let diagnostics = diagnostics_for(
+            "projects:\n  p:\n    auth: { type: Inherit }\n    environments:\n      dev: { base_url: 'http://localhost/' }\n    endpoints:\n      e: { url_path: '/', auth: { type: Inherit } }\n",
+        );
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                "error: project 'p': auth is Inherit, but a project has nothing to inherit from",
+                "error: project 'p', endpoint 'e': inherits auth, but environment 'dev' has none",
+            ]
+        );
+    }
+}